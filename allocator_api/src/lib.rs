@@ -0,0 +1,42 @@
+use std::alloc::{alloc, dealloc, Layout};
+
+/// A stable stand-in for std's unstable Allocator trait, so channels and
+/// reference counted values can live in an arena instead of the global allocator.
+///
+/// # Safety
+///
+/// Memory returned by `allocate` has to stay valid until it is passed
+/// to `deallocate` on the same allocator or a clone of it.
+pub unsafe trait Allocator {
+    // returns null if the allocation failed, layout is never zero sized
+    fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// # Safety
+    ///
+    /// `ptr` was returned by `allocate` on this allocator with the same layout.
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+}
+
+// the global allocator, what Box uses
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Global;
+
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        dealloc(ptr, layout)
+    }
+}
+
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
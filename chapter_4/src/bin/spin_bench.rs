@@ -0,0 +1,106 @@
+use chapter_4::{
+    mcs_lock::McsLock,
+    spin_lock::{Backoff, SpinLock},
+    ticket_lock::TicketLock,
+};
+use std::{
+    cell::UnsafeCell,
+    hint::black_box,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::SystemTime,
+};
+
+// the lock as it was before, every spin is a swap
+struct SwapLock {
+    locked: AtomicBool,
+    value: UnsafeCell<usize>,
+}
+
+unsafe impl Sync for SwapLock {}
+
+impl SwapLock {
+    fn increment(&self) {
+        while self.locked.swap(true, Ordering::Acquire) {
+            std::hint::spin_loop();
+        }
+        unsafe { *black_box(self.value.get()) += 1 };
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+const ITERATIONS: usize = 1_000_000;
+
+fn main() {
+    // fair locks hand the lock to a waiter that might not be running,
+    // so don't start more threads than there are cores
+    let threads = thread::available_parallelism()
+        .map_or(4, |n| n.get())
+        .min(4);
+    println!("{threads} threads");
+
+    let start_time = SystemTime::now();
+    let lock = SwapLock {
+        locked: AtomicBool::new(false),
+        value: UnsafeCell::new(0),
+    };
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    lock.increment();
+                }
+            });
+        }
+    });
+    assert_eq!(lock.value.into_inner(), threads * ITERATIONS);
+    println!("swap: {:?}", start_time.elapsed());
+
+    for (name, backoff) in [
+        ("test and test and set", Backoff::new().spin_limit(0)),
+        ("exponential backoff", Backoff::new()),
+        ("backoff and yield", Backoff::new().yield_after(10)),
+    ] {
+        let start_time = SystemTime::now();
+        let lock = SpinLock::with_backoff(0, backoff);
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    for _ in 0..ITERATIONS {
+                        *black_box(&mut *lock.lock()) += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.into_inner(), threads * ITERATIONS);
+        println!("{name}: {:?}", start_time.elapsed());
+    }
+
+    let start_time = SystemTime::now();
+    let lock = TicketLock::new(0);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    *black_box(&mut *lock.lock()) += 1;
+                }
+            });
+        }
+    });
+    assert_eq!(lock.into_inner(), threads * ITERATIONS);
+    println!("ticket: {:?}", start_time.elapsed());
+
+    let start_time = SystemTime::now();
+    let lock = McsLock::new(0);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    *black_box(&mut *lock.lock()) += 1;
+                }
+            });
+        }
+    });
+    assert_eq!(lock.into_inner(), threads * ITERATIONS);
+    println!("mcs: {:?}", start_time.elapsed());
+}
@@ -0,0 +1,5 @@
+pub mod mcs_lock;
+pub mod rw_lock;
+pub mod seq_lock;
+pub mod spin_lock;
+pub mod ticket_lock;
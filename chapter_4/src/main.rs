@@ -1,9 +1,5 @@
-use std::ops::{Deref, DerefMut};
+use chapter_4::spin_lock::SpinLock;
 use std::thread;
-use std::{
-    cell::UnsafeCell,
-    sync::atomic::{AtomicBool, Ordering},
-};
 
 fn main() {
     println!("Hello, spin lock!");
@@ -23,50 +19,3 @@ fn main() {
     let guard = l.lock();
     assert!(guard.as_slice() == [1,1,2] || guard.as_slice() == [1, 2, 1]);
 }
-
-struct SpinLock<T> {
-    locked: AtomicBool,
-    value: UnsafeCell<T>,
-}
-
-unsafe impl<T> Sync for SpinLock<T> where T: Send {}
-
-impl<T> SpinLock<T> {
-    pub const fn new(value: T) -> Self {
-        Self {
-            locked: AtomicBool::new(false),
-            value: UnsafeCell::new(value),
-        }
-    }
-
-    pub fn lock(&self) -> Guard<T> {
-        while self.locked.swap(true, Ordering::Acquire) {
-            std::hint::spin_loop();
-        }
-        Guard { lock: self }
-    }
-}
-
-pub struct Guard<'a, T> {
-    lock: &'a SpinLock<T>,
-}
-
-impl<T> Deref for Guard<'_, T> {
-    type Target = T;
-    fn deref(&self) -> &T {
-        unsafe { &*self.lock.value.get() }
-    }
-}
-
-
-impl<T> DerefMut for Guard<'_, T> {
-    fn deref_mut(&mut self) -> &mut T {
-       unsafe {&mut *self.lock.value.get() } 
-    }
-}
-
-impl<T> Drop for Guard<'_, T> {
-    fn drop(&mut self) {
-        self.lock.locked.store(false, Ordering::Release);
-    }
-}
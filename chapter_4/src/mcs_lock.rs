@@ -0,0 +1,234 @@
+use std::{
+    cell::{RefCell, UnsafeCell},
+    hint,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+// a waiter in the queue, aligned so every waiter spins on a cache line of its own
+#[repr(align(64))]
+struct Node {
+    next: AtomicPtr<Node>,
+    locked: AtomicBool,
+}
+
+// nodes this thread is done with, one for each lock it held at the same time
+struct Nodes(RefCell<Vec<NonNull<Node>>>);
+
+impl Drop for Nodes {
+    fn drop(&mut self) {
+        for node in self.0.get_mut().drain(..) {
+            drop(unsafe { Box::from_raw(node.as_ptr()) });
+        }
+    }
+}
+
+thread_local! {
+    static NODES: Nodes = const { Nodes(RefCell::new(Vec::new())) };
+}
+
+// the node is boxed so it stays in place when the guard moves
+fn take_node() -> NonNull<Node> {
+    match NODES.try_with(|nodes| nodes.0.borrow_mut().pop()) {
+        Ok(Some(node)) => {
+            let n = unsafe { node.as_ref() };
+            n.next.store(ptr::null_mut(), Ordering::Relaxed);
+            n.locked.store(true, Ordering::Relaxed);
+            node
+        }
+        _ => NonNull::from(Box::leak(Box::new(Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            locked: AtomicBool::new(true),
+        }))),
+    }
+}
+
+// SAFETY: the node has to come from take_node and no other thread may still use it
+unsafe fn return_node(node: NonNull<Node>) {
+    if NODES
+        .try_with(|nodes| nodes.0.borrow_mut().push(node))
+        .is_err()
+    {
+        // the thread is exiting, nothing left to keep it for
+        drop(Box::from_raw(node.as_ptr()));
+    }
+}
+
+// a queue lock: threads line up behind `tail` and every thread spins on its own
+// node until the one before it hands over the lock, so a release only touches
+// the cache line of the next waiter and the lock is granted in FIFO order
+pub struct McsLock<T> {
+    tail: AtomicPtr<Node>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for McsLock<T> where T: Send {}
+
+impl<T> McsLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            tail: AtomicPtr::new(ptr::null_mut()),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> McsGuard<'_, T> {
+        let node = take_node();
+        let prev = self.tail.swap(node.as_ptr(), Ordering::AcqRel);
+        if !prev.is_null() {
+            // the previous thread can't free its node before it has seen us
+            unsafe { (*prev).next.store(node.as_ptr(), Ordering::Release) };
+            while unsafe { node.as_ref() }.locked.load(Ordering::Acquire) {
+                hint::spin_loop();
+            }
+        }
+        McsGuard { lock: self, node }
+    }
+
+    pub fn try_lock(&self) -> Option<McsGuard<'_, T>> {
+        if !self.tail.load(Ordering::Relaxed).is_null() {
+            return None;
+        }
+        let node = take_node();
+        match self.tail.compare_exchange(
+            ptr::null_mut(),
+            node.as_ptr(),
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Some(McsGuard { lock: self, node }),
+            Err(_) => {
+                unsafe { return_node(node) };
+                None
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct McsGuard<'a, T> {
+    lock: &'a McsLock<T>,
+    node: NonNull<Node>,
+}
+
+impl<T> Deref for McsGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for McsGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for McsGuard<'_, T> {
+    fn drop(&mut self) {
+        let node = unsafe { self.node.as_ref() };
+        let mut next = node.next.load(Ordering::Acquire);
+        if next.is_null() {
+            // nobody behind us, unless someone swapped the tail but didn't link up yet
+            if self
+                .lock
+                .tail
+                .compare_exchange(
+                    self.node.as_ptr(),
+                    ptr::null_mut(),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                unsafe { return_node(self.node) };
+                return;
+            }
+            while next.is_null() {
+                hint::spin_loop();
+                next = node.next.load(Ordering::Acquire);
+            }
+        }
+        unsafe { (*next).locked.store(false, Ordering::Release) };
+        // the thread behind us linked up already, nobody touches our node anymore
+        unsafe { return_node(self.node) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_mcs_lock() {
+        let mut lock = McsLock::new(0);
+        let guard = lock.lock();
+        assert!(lock.try_lock().is_none());
+        drop(guard);
+
+        let iterations = if cfg!(miri) { 50 } else { 1000 };
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..iterations {
+                        *lock.lock() += 1;
+                    }
+                });
+            }
+        });
+        *lock.try_lock().unwrap() += 1;
+        *lock.get_mut() += 1;
+        assert_eq!(lock.into_inner(), 4 * iterations + 2);
+    }
+
+    #[test]
+    fn test_node_reuse() {
+        let a = McsLock::new(0);
+        let b = McsLock::new(0);
+
+        let guard = a.lock();
+        let node = guard.node;
+        drop(guard);
+        // locking again takes the node back instead of allocating
+        let guard = a.lock();
+        assert_eq!(guard.node, node);
+
+        // while it's in use another lock needs a node of its own
+        let other = b.lock();
+        assert_ne!(other.node, node);
+        assert!(b.try_lock().is_none());
+        drop(other);
+        drop(guard);
+        assert_eq!(a.try_lock().unwrap().node, node);
+    }
+
+    #[test]
+    fn test_fifo() {
+        let lock = McsLock::new(Vec::new());
+        let guard = lock.lock();
+        thread::scope(|s| {
+            for i in 0..4 {
+                let tail = lock.tail.load(Ordering::Relaxed);
+                s.spawn({
+                    let lock = &lock;
+                    move || lock.lock().push(i)
+                });
+                // make sure thread i is queued before the next one starts
+                while lock.tail.load(Ordering::Relaxed) == tail {
+                    hint::spin_loop();
+                }
+            }
+            drop(guard);
+        });
+        assert_eq!(lock.into_inner(), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+// the RwLock from chapter 9 without the futex, waiting is done by spinning
+pub struct SpinRwLock<T> {
+    // number of readers * 2 + 1 if a writer is waiting, or u32::MAX if write locked
+    state: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for SpinRwLock<T> where T: Send + Sync {}
+
+impl<T> SpinRwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            // new readers stay out while a writer is waiting or holds the lock
+            if s.is_multiple_of(2) {
+                assert!(s != u32::MAX - 1, "too many readers");
+                match self.state.compare_exchange_weak(
+                    s,
+                    s + 2,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return ReadGuard { lock: self },
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+            hint::spin_loop();
+            s = self.state.load(Ordering::Relaxed);
+        }
+    }
+
+    pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s.is_multiple_of(2) {
+            assert!(s != u32::MAX - 1, "too many readers");
+            match self
+                .state
+                .compare_exchange_weak(s, s + 2, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(ReadGuard { lock: self }),
+                Err(e) => s = e,
+            }
+        }
+        None
+    }
+
+    pub fn write(&self) -> WriteGuard<'_, T> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            // no readers left, a waiting writer bit might be ours or another writer's
+            if s <= 1 {
+                match self
+                    .state
+                    .compare_exchange(s, u32::MAX, Ordering::Acquire, Ordering::Relaxed)
+                {
+                    Ok(_) => return WriteGuard { lock: self },
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+            // block new readers so they can't starve us
+            if s.is_multiple_of(2) {
+                if let Err(e) =
+                    self.state
+                        .compare_exchange(s, s + 1, Ordering::Relaxed, Ordering::Relaxed)
+                {
+                    s = e;
+                    continue;
+                }
+            }
+            hint::spin_loop();
+            s = self.state.load(Ordering::Relaxed);
+        }
+    }
+
+    pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+        let s = self.state.load(Ordering::Relaxed);
+        if s > 1 {
+            return None;
+        }
+        self.state
+            .compare_exchange(s, u32::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| WriteGuard { lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct ReadGuard<'a, T> {
+    lock: &'a SpinRwLock<T>,
+}
+
+pub struct WriteGuard<'a, T> {
+    lock: &'a SpinRwLock<T>,
+}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(2, Ordering::Release);
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_read_write() {
+        let lock = SpinRwLock::new(0);
+
+        let a = lock.read();
+        let b = lock.read();
+        assert_eq!(*a + *b, 0);
+        assert!(lock.try_write().is_none());
+        drop((a, b));
+
+        let mut w = lock.write();
+        *w += 1;
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(w);
+
+        assert_eq!(*lock.try_read().unwrap(), 1);
+        assert_eq!(lock.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_writer_not_starved() {
+        let lock = SpinRwLock::new(Vec::new());
+
+        let r = lock.read();
+        thread::scope(|s| {
+            s.spawn(|| lock.write().push(1));
+            // wait for the writer to announce itself, after that new readers have to wait
+            while lock.state.load(Ordering::Relaxed) != 3 {
+                hint::spin_loop();
+            }
+            assert!(lock.try_read().is_none());
+            drop(r);
+        });
+        assert_eq!(*lock.read(), [1]);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let mut lock = SpinRwLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.write() += 1;
+                        assert!(*lock.read() > 0);
+                    }
+                });
+            }
+        });
+        *lock.get_mut() += 1;
+        assert_eq!(lock.into_inner(), 4001);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::spin_lock::SpinLock;
+use std::{
+    cell::UnsafeCell,
+    hint,
+    mem::{self, MaybeUninit},
+    ptr,
+    sync::{
+        atomic::{fence, AtomicU8, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+// values a SeqLock can hold. readers copy the value while it might be written, which
+// is only sound with atomics, and atomics can't copy padding bytes. so a value is
+// stored as a `Repr` made of primitives, arrays and tuples, which are copied one
+// primitive at a time. any other Copy type opts in by converting to one of those
+pub trait SeqLockValue: Copy {
+    type Repr: AtomicCopy;
+
+    fn into_repr(self) -> Self::Repr;
+    fn from_repr(repr: Self::Repr) -> Self;
+}
+
+mod sealed {
+    // SAFETY for both: the pointers are valid and the memory behind them is
+    // only accessed through load and store while other threads can see it
+    pub trait AtomicCopy: Copy {
+        unsafe fn load(src: *mut Self, dst: *mut Self);
+        unsafe fn store(dst: *mut Self, value: Self);
+    }
+}
+
+use sealed::AtomicCopy;
+
+// a value is copied in words if its alignment allows it and the rest byte by byte.
+// readers and the writer split it up the same way, so no two atomics partially overlap
+fn words<T>() -> usize {
+    if mem::align_of::<T>() >= mem::align_of::<AtomicUsize>() {
+        mem::size_of::<T>() / mem::size_of::<usize>()
+    } else {
+        0
+    }
+}
+
+macro_rules! primitive {
+    ($($t:ty),*) => {$(
+        impl SeqLockValue for $t {
+            type Repr = Self;
+
+            fn into_repr(self) -> Self {
+                self
+            }
+
+            fn from_repr(repr: Self) -> Self {
+                repr
+            }
+        }
+
+        // primitives have no padding, so every byte can go through an atomic
+        impl AtomicCopy for $t {
+            unsafe fn load(src: *mut Self, dst: *mut Self) {
+                let (src, dst) = (src as *mut u8, dst as *mut u8);
+                let words = words::<Self>();
+                for i in 0..words {
+                    let offset = i * mem::size_of::<usize>();
+                    let word = AtomicUsize::from_ptr(src.add(offset) as *mut usize).load(Ordering::Relaxed);
+                    (dst.add(offset) as *mut usize).write(word);
+                }
+                for i in words * mem::size_of::<usize>()..mem::size_of::<Self>() {
+                    dst.add(i)
+                        .write(AtomicU8::from_ptr(src.add(i)).load(Ordering::Relaxed));
+                }
+            }
+
+            unsafe fn store(dst: *mut Self, value: Self) {
+                let (src, dst) = (&value as *const Self as *const u8, dst as *mut u8);
+                let words = words::<Self>();
+                for i in 0..words {
+                    let offset = i * mem::size_of::<usize>();
+                    let word = (src.add(offset) as *const usize).read();
+                    AtomicUsize::from_ptr(dst.add(offset) as *mut usize).store(word, Ordering::Relaxed);
+                }
+                for i in words * mem::size_of::<usize>()..mem::size_of::<Self>() {
+                    AtomicU8::from_ptr(dst.add(i)).store(src.add(i).read(), Ordering::Relaxed);
+                }
+            }
+        }
+    )*};
+}
+
+primitive!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char);
+
+impl<T: SeqLockValue, const N: usize> SeqLockValue for [T; N] {
+    type Repr = [T::Repr; N];
+
+    fn into_repr(self) -> Self::Repr {
+        self.map(T::into_repr)
+    }
+
+    fn from_repr(repr: Self::Repr) -> Self {
+        repr.map(T::from_repr)
+    }
+}
+
+impl<T: AtomicCopy, const N: usize> AtomicCopy for [T; N] {
+    unsafe fn load(src: *mut Self, dst: *mut Self) {
+        for i in 0..N {
+            T::load((src as *mut T).add(i), (dst as *mut T).add(i));
+        }
+    }
+
+    unsafe fn store(dst: *mut Self, value: Self) {
+        for (i, value) in value.into_iter().enumerate() {
+            T::store((dst as *mut T).add(i), value);
+        }
+    }
+}
+
+// tuples are copied field by field, which skips the padding between the fields
+macro_rules! tuple {
+    ($($t:ident $i:tt),*) => {
+        impl<$($t: SeqLockValue),*> SeqLockValue for ($($t,)*) {
+            type Repr = ($($t::Repr,)*);
+
+            fn into_repr(self) -> Self::Repr {
+                ($(self.$i.into_repr(),)*)
+            }
+
+            fn from_repr(repr: Self::Repr) -> Self {
+                ($($t::from_repr(repr.$i),)*)
+            }
+        }
+
+        impl<$($t: AtomicCopy),*> AtomicCopy for ($($t,)*) {
+            unsafe fn load(src: *mut Self, dst: *mut Self) {
+                $($t::load(ptr::addr_of_mut!((*src).$i), ptr::addr_of_mut!((*dst).$i));)*
+            }
+
+            unsafe fn store(dst: *mut Self, value: Self) {
+                $($t::store(ptr::addr_of_mut!((*dst).$i), value.$i);)*
+            }
+        }
+    };
+}
+
+tuple!(A 0, B 1);
+tuple!(A 0, B 1, C 2);
+tuple!(A 0, B 1, C 2, D 3);
+
+impl SeqLockValue for Duration {
+    type Repr = (u64, u32);
+
+    fn into_repr(self) -> Self::Repr {
+        (self.as_secs(), self.subsec_nanos())
+    }
+
+    fn from_repr((secs, nanos): Self::Repr) -> Self {
+        Duration::new(secs, nanos)
+    }
+}
+
+// an Instant can't be taken apart, so it is stored as its distance to a fixed
+// instant of this process, and whether it comes before or after it
+static ANCHOR: OnceLock<Instant> = OnceLock::new();
+
+impl SeqLockValue for Instant {
+    type Repr = (bool, <Duration as SeqLockValue>::Repr);
+
+    fn into_repr(self) -> Self::Repr {
+        let anchor = *ANCHOR.get_or_init(Instant::now);
+        match self.checked_duration_since(anchor) {
+            Some(after) => (true, after.into_repr()),
+            None => (false, (anchor - self).into_repr()),
+        }
+    }
+
+    fn from_repr((after, distance): Self::Repr) -> Self {
+        let anchor = *ANCHOR.get().unwrap();
+        let distance = Duration::from_repr(distance);
+        if after {
+            anchor + distance
+        } else {
+            anchor - distance
+        }
+    }
+}
+
+// readers never block the writer: they copy the value and check that the sequence
+// didn't change in the meantime, the sequence is odd while a write is in progress.
+// a reader can copy a value that is being written, so the copy is done with relaxed
+// atomics and only looked at once the sequence says it wasn't torn
+pub struct SeqLock<T: SeqLockValue> {
+    seq: AtomicUsize,
+    // writers still exclude each other
+    writer: SpinLock<()>,
+    value: UnsafeCell<T::Repr>,
+}
+
+unsafe impl<T: SeqLockValue + Send> Sync for SeqLock<T> {}
+
+impl<T: SeqLockValue> SeqLock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            writer: SpinLock::new(()),
+            value: UnsafeCell::new(value.into_repr()),
+        }
+    }
+
+    pub fn read(&self) -> T {
+        loop {
+            let s1 = self.seq.load(Ordering::Acquire);
+            if !s1.is_multiple_of(2) {
+                hint::spin_loop();
+                continue;
+            }
+
+            // MaybeUninit because a torn copy might not be a valid T
+            let mut value = MaybeUninit::uninit();
+            unsafe { T::Repr::load(self.value.get(), value.as_mut_ptr()) };
+
+            // like the fence in chapter 3, makes the Relaxed load below see
+            // any write that our copy might have seen parts of
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == s1 {
+                return T::from_repr(unsafe { value.assume_init() });
+            }
+        }
+    }
+
+    pub fn write(&self, value: T) {
+        self.update(|_| value);
+    }
+
+    // replaces the value with `f` of the current one, readers see either the old or the new value
+    pub fn update(&self, f: impl FnOnce(T) -> T) {
+        let _guard = self.writer.lock();
+        // nobody else writes while we hold the lock, so this copy can't be torn
+        let mut value = MaybeUninit::uninit();
+        unsafe { T::Repr::load(self.value.get(), value.as_mut_ptr()) };
+        let value = f(T::from_repr(unsafe { value.assume_init() })).into_repr();
+
+        let s = self.seq.load(Ordering::Relaxed);
+        self.seq.store(s.wrapping_add(1), Ordering::Relaxed);
+        // the odd sequence has to be visible before any part of the new value
+        fence(Ordering::Release);
+        unsafe { T::Repr::store(self.value.get(), value) };
+        self.seq.store(s.wrapping_add(2), Ordering::Release);
+    }
+
+    pub fn into_inner(self) -> T {
+        T::from_repr(self.value.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_seq_lock() {
+        let lock = SeqLock::new([0u64, 0u64]);
+        assert_eq!(lock.read(), [0, 0]);
+
+        lock.write([1, 1]);
+        lock.update(|[a, b]| [a + 1, b + 1]);
+        assert_eq!(lock.read(), [2, 2]);
+        assert_eq!(lock.seq.load(Ordering::Relaxed), 4);
+        assert_eq!(lock.into_inner(), [2, 2]);
+
+        // copied byte by byte, the alignment is too small for words
+        let lock = SeqLock::new([1u8, 2, 3]);
+        lock.update(|[a, b, c]| [c, b, a]);
+        assert_eq!(lock.read(), [3, 2, 1]);
+    }
+
+    #[test]
+    fn test_padded_values() {
+        // the padding after the u32 is never copied
+        let lock = SeqLock::new((1u64, 2u32));
+        lock.update(|(a, b)| (a + 1, b + 1));
+        assert_eq!(lock.read(), (2, 3));
+
+        let lock = SeqLock::new(Duration::new(1, 500));
+        lock.update(|d| d + Duration::from_millis(1));
+        assert_eq!(lock.read(), Duration::new(1, 1_000_500));
+
+        let now = Instant::now();
+        let earlier = now - Duration::from_millis(10);
+        let lock = SeqLock::new((now, 0u8));
+        assert_eq!(lock.read(), (now, 0));
+        lock.write((earlier, 1));
+        assert_eq!(lock.into_inner(), (earlier, 1));
+    }
+
+    #[test]
+    fn test_no_torn_reads() {
+        let iterations = if cfg!(miri) { 100 } else { 10_000 };
+        let lock = SeqLock::new([0usize; 8]);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..iterations {
+                        lock.update(|v| [v[0] + 1; 8]);
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..iterations {
+                        let v = lock.read();
+                        assert!(v.iter().all(|&n| n == v[0]));
+                        assert!(v[0] >= last);
+                        last = v[0];
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.read(), [2 * iterations; 8]);
+    }
+}
@@ -0,0 +1,278 @@
+use std::ops::{Deref, DerefMut};
+use std::{
+    cell::UnsafeCell,
+    hint,
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+};
+
+// how a waiting thread spins: after every failed attempt it spins twice as
+// long as before, up to 2^spin_limit times, and once `yield_after` attempts
+// have failed it gives its time slice to other threads instead.
+// spin_limit can be at most 31, 2^32 spins wouldn't fit into the loop counter
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    spin_limit: u32,
+    yield_after: Option<u32>,
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self {
+            spin_limit: 6,
+            yield_after: None,
+        }
+    }
+
+    pub const fn spin_limit(self, spin_limit: u32) -> Self {
+        let spin_limit = if spin_limit > 31 { 31 } else { spin_limit };
+        Self { spin_limit, ..self }
+    }
+
+    pub const fn yield_after(self, attempts: u32) -> Self {
+        Self {
+            yield_after: Some(attempts),
+            ..self
+        }
+    }
+
+    fn snooze(&self, attempt: &mut u32) {
+        if self.yield_after.is_some_and(|n| *attempt >= n) {
+            thread::yield_now();
+        } else {
+            for _ in 0..1u32 << (*attempt).min(self.spin_limit) {
+                hint::spin_loop();
+            }
+        }
+        *attempt = attempt.saturating_add(1);
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SpinLock<T> {
+    // 0 is unlocked
+    // 1 is locked
+    state: AtomicU32,
+    // threads waiting in lock, bump only gives the lock away if there are any
+    spinning: AtomicU32,
+    backoff: Backoff,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for SpinLock<T> where T: Send {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self::with_backoff(value, Backoff::new())
+    }
+
+    pub const fn with_backoff(value: T, backoff: Backoff) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            spinning: AtomicU32::new(0),
+            backoff,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> Guard<'_, T> {
+        if self
+            .state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+        Guard { lock: self }
+    }
+
+    #[cold]
+    fn lock_contended(&self) {
+        let mut attempt = 0;
+        self.spinning.fetch_add(1, Ordering::Relaxed);
+        // only try to take the lock when it looks free, a swap writes the
+        // cache line and takes it away from everybody else that is spinning
+        while self.state.swap(1, Ordering::Acquire) != 0 {
+            while self.state.load(Ordering::Relaxed) != 0 {
+                self.backoff.snooze(&mut attempt);
+            }
+        }
+        self.spinning.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn try_lock(&self) -> Option<Guard<'_, T>> {
+        self.state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| Guard { lock: self })
+    }
+
+    // no guards can exist while we have the lock itself
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct Guard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Guard<'_, T> {
+    // runs `f` without holding the lock and locks it again afterwards
+    pub fn unlocked<U>(guard: &mut Self, f: impl FnOnce() -> U) -> U {
+        // locks again even if `f` panics, the guard still gets dropped after all
+        struct Relock<'a, T>(&'a SpinLock<T>);
+
+        impl<T> Drop for Relock<'_, T> {
+            fn drop(&mut self) {
+                if self
+                    .0
+                    .state
+                    .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+                {
+                    self.0.lock_contended();
+                }
+            }
+        }
+
+        guard.lock.state.store(0, Ordering::Release);
+        let _relock = Relock(guard.lock);
+        f()
+    }
+
+    // lets a spinning thread have the lock first, if there could be one
+    pub fn bump(guard: &mut Self) {
+        if guard.lock.spinning.load(Ordering::Relaxed) != 0 {
+            Guard::unlocked(guard, thread::yield_now);
+        }
+    }
+}
+
+impl<T> Deref for Guard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for Guard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_lock() {
+        let mut l = SpinLock::new(0);
+
+        let mut guard = l.try_lock().unwrap();
+        *guard += 1;
+        assert!(l.try_lock().is_none());
+        drop(guard);
+
+        thread::scope(|s| {
+            s.spawn(|| *l.try_lock().unwrap() += 1);
+        });
+        *l.get_mut() += 1;
+        assert_eq!(l.into_inner(), 3);
+    }
+
+    #[test]
+    fn test_backoff() {
+        for backoff in [
+            Backoff::new(),
+            Backoff::new().spin_limit(0),
+            Backoff::new().spin_limit(2).yield_after(4),
+        ] {
+            let l = SpinLock::with_backoff(0, backoff);
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..1000 {
+                            *l.lock() += 1;
+                        }
+                    });
+                }
+            });
+            assert_eq!(l.into_inner(), 4000);
+        }
+
+        // a larger limit would overflow the shift in snooze
+        assert_eq!(Backoff::new().spin_limit(32).spin_limit, 31);
+        assert_eq!(Backoff::new().spin_limit(u32::MAX).spin_limit, 31);
+    }
+
+    #[test]
+    fn test_unlocked() {
+        let l = SpinLock::new(0);
+        let mut guard = l.lock();
+        *guard += 1;
+        Guard::unlocked(&mut guard, || {
+            *l.try_lock().unwrap() += 1;
+        });
+        *guard += 1;
+        assert!(l.try_lock().is_none());
+        drop(guard);
+
+        // the lock is held again after a panic, and released with the guard
+        let mut guard = l.lock();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            Guard::unlocked(&mut guard, || panic!("in unlocked"))
+        }));
+        assert!(result.is_err());
+        assert!(l.try_lock().is_none());
+        drop(guard);
+        assert_eq!(*l.lock(), 3);
+    }
+
+    #[test]
+    fn test_bump() {
+        let l = SpinLock::new(Vec::new());
+
+        // nobody waiting, the lock isn't released at all
+        let mut guard = l.lock();
+        Guard::bump(&mut guard);
+        assert_eq!(l.spinning.load(Ordering::Relaxed), 0);
+        // relocking after unlocked doesn't count as a waiter either
+        Guard::unlocked(&mut guard, || {});
+        assert_eq!(l.spinning.load(Ordering::Relaxed), 0);
+        Guard::bump(&mut guard);
+        assert!(l.try_lock().is_none());
+        drop(guard);
+
+        let mut guard = l.lock();
+        thread::scope(|s| {
+            s.spawn(|| l.lock().push(1));
+            while l.spinning.load(Ordering::Relaxed) == 0 {
+                hint::spin_loop();
+            }
+            // keep bumping until the other thread got its turn
+            while guard.is_empty() {
+                Guard::bump(&mut guard);
+            }
+            guard.push(2);
+            drop(guard);
+        });
+        assert_eq!(l.into_inner(), [1, 2]);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+// a fair spin lock, every thread draws a ticket and waits until it is served,
+// so the lock is handed out in the order lock was called
+pub struct TicketLock<T> {
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for TicketLock<T> where T: Send {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> TicketGuard<'_, T> {
+        // the counters wrap around, which is fine as long as there
+        // are fewer than 2^32 threads waiting
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            hint::spin_loop();
+        }
+        TicketGuard { lock: self, ticket }
+    }
+
+    pub fn try_lock(&self) -> Option<TicketGuard<'_, T>> {
+        // only take a ticket if it would be served right away
+        let ticket = self.now_serving.load(Ordering::Acquire);
+        self.next_ticket
+            .compare_exchange(
+                ticket,
+                ticket.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| TicketGuard { lock: self, ticket })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct TicketGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+    ticket: u32,
+}
+
+impl<T> Deref for TicketGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for TicketGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for TicketGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock
+            .now_serving
+            .store(self.ticket.wrapping_add(1), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_ticket_lock() {
+        let mut lock = TicketLock::new(0);
+        let guard = lock.lock();
+        assert!(lock.try_lock().is_none());
+        drop(guard);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.lock() += 1;
+                    }
+                });
+            }
+        });
+        *lock.try_lock().unwrap() += 1;
+        *lock.get_mut() += 1;
+        assert_eq!(lock.into_inner(), 4002);
+    }
+
+    #[test]
+    fn test_fifo() {
+        let lock = TicketLock::new(Vec::new());
+        let guard = lock.lock();
+        thread::scope(|s| {
+            for i in 0..4 {
+                s.spawn({
+                    let lock = &lock;
+                    move || lock.lock().push(i)
+                });
+                // make sure thread i has its ticket before the next one starts
+                while lock.next_ticket.load(Ordering::Relaxed) != i + 2 {
+                    hint::spin_loop();
+                }
+            }
+            drop(guard);
+        });
+        assert_eq!(lock.into_inner(), [0, 1, 2, 3]);
+    }
+}
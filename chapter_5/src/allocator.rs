@@ -0,0 +1,84 @@
+use allocator_api::{Allocator, Global};
+use std::{
+    alloc::{handle_alloc_error, Layout},
+    ops::Deref,
+    ptr::{self, NonNull},
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+// the bare minimum of an Arc that allocates through `A`,
+// std's Arc::new_in is still unstable
+pub struct Shared<T, A: Allocator = Global> {
+    pointer: NonNull<SharedData<T>>,
+    alloc: A,
+}
+
+struct SharedData<T> {
+    ref_count: AtomicUsize,
+    data: T,
+}
+
+unsafe impl<T: Send + Sync, A: Allocator + Send> Send for Shared<T, A> {}
+unsafe impl<T: Send + Sync, A: Allocator + Sync> Sync for Shared<T, A> {}
+
+// like Arc, moving the pointer never moves the data
+impl<T, A: Allocator> Unpin for Shared<T, A> {}
+
+impl<T, A: Allocator> Shared<T, A> {
+    pub fn new_in(data: T, alloc: A) -> Self {
+        let layout = Layout::new::<SharedData<T>>();
+        let ptr = alloc.allocate(layout) as *mut SharedData<T>;
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        unsafe {
+            ptr.write(SharedData {
+                ref_count: AtomicUsize::new(1),
+                data,
+            });
+            Shared {
+                pointer: NonNull::new_unchecked(ptr),
+                alloc,
+            }
+        }
+    }
+
+    fn shared(&self) -> &SharedData<T> {
+        unsafe { self.pointer.as_ref() }
+    }
+}
+
+impl<T, A: Allocator> Deref for Shared<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.shared().data
+    }
+}
+
+impl<T, A: Allocator + Clone> Clone for Shared<T, A> {
+    fn clone(&self) -> Self {
+        if self.shared().ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        Shared {
+            pointer: self.pointer,
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+impl<T, A: Allocator> Drop for Shared<T, A> {
+    fn drop(&mut self) {
+        if self.shared().ref_count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            unsafe {
+                ptr::drop_in_place(self.pointer.as_ptr());
+                self.alloc.deallocate(
+                    self.pointer.as_ptr() as *mut u8,
+                    Layout::new::<SharedData<T>>(),
+                );
+            }
+        }
+    }
+}
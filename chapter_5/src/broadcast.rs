@@ -0,0 +1,321 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    task::{Poll, Waker},
+};
+
+use crate::select::{SelectSend, Selectable};
+
+struct State<T> {
+    // the last `capacity` messages, oldest first
+    buffer: VecDeque<T>,
+    capacity: usize,
+    // sequence number of the oldest message still in the buffer
+    first: u64,
+    senders: usize,
+    receivers: usize,
+    // receivers waiting in a `select`
+    wakers: Vec<Waker>,
+}
+
+impl<T> State<T> {
+    // sequence number the next message will get
+    fn next(&self) -> u64 {
+        self.first + self.buffer.len() as u64
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Clone> State<T> {
+    fn read(&self, cursor: &mut u64) -> Result<T, TryRecvError> {
+        if *cursor < self.first {
+            // skip to the oldest message we still have
+            let missed = self.first - *cursor;
+            *cursor = self.first;
+            return Err(TryRecvError::Lagged(missed));
+        }
+        if *cursor < self.next() {
+            let value = self.buffer[(*cursor - self.first) as usize].clone();
+            *cursor += 1;
+            return Ok(value);
+        }
+        if self.senders == 0 {
+            return Err(TryRecvError::Closed);
+        }
+        Err(TryRecvError::Empty)
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    is_ready: Condvar,
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // sequence number of the next message this receiver will see
+    cursor: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvError {
+    // the receiver fell behind and this many messages were overwritten
+    Lagged(u64),
+    // all senders are gone and every message has been received
+    Closed,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Lagged(u64),
+    Closed,
+}
+
+#[allow(dead_code)]
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be greater than zero");
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            first: 0,
+            senders: 1,
+            receivers: 1,
+            wakers: Vec::new(),
+        }),
+        is_ready: Condvar::new(),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, cursor: 0 },
+    )
+}
+
+#[allow(dead_code)]
+impl<T: Clone> Sender<T> {
+    // returns the value back if there is nobody to receive it
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        if state.buffer.len() == state.capacity {
+            state.buffer.pop_front();
+            state.first += 1;
+        }
+        state.buffer.push_back(value);
+        state.wake_all();
+        drop(state);
+
+        self.shared.is_ready.notify_all();
+        Ok(())
+    }
+
+    // the new receiver only sees messages sent after this call
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            cursor: state.next(),
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.shared.state.lock().unwrap().receivers
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.wake_all();
+            drop(state);
+            // wake everyone up so they can see the channel is closed
+            self.shared.is_ready.notify_all();
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: Clone> Receiver<T> {
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            match state.read(&mut self.cursor) {
+                Err(TryRecvError::Empty) => state = self.shared.is_ready.wait(state).unwrap(),
+                Err(TryRecvError::Lagged(n)) => return Err(RecvError::Lagged(n)),
+                Err(TryRecvError::Closed) => return Err(RecvError::Closed),
+                Ok(value) => return Ok(value),
+            }
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let state = self.shared.state.lock().unwrap();
+        state.read(&mut self.cursor)
+    }
+}
+
+impl<T: Clone> Selectable for &mut Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll_select(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.read(&mut self.cursor) {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Lagged(n)) => Poll::Ready(Err(RecvError::Lagged(n))),
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
+            Err(TryRecvError::Empty) => {
+                if !state.wakers.iter().any(|w| w.will_wake(waker)) {
+                    state.wakers.push(waker.clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T: Clone> SelectSend<T> for &Sender<T> {
+    type Output = Result<(), SendError<T>>;
+
+    // the buffer overwrites old messages, so sending never has to wait
+    fn poll_send(&mut self, value: T, _waker: &Waker) -> Result<Self::Output, T> {
+        Ok(self.send(value))
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receivers -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::select::select;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_every_receiver_sees_every_message() {
+        let (sender, receiver) = channel(16);
+        let receivers: Vec<_> = (0..3).map(|_| sender.subscribe()).chain([receiver]).collect();
+
+        thread::scope(|s| {
+            for mut receiver in receivers {
+                s.spawn(move || {
+                    let mut received = Vec::new();
+                    while let Ok(n) = receiver.recv() {
+                        received.push(n);
+                    }
+                    assert_eq!(received, (0..10).collect::<Vec<_>>());
+                });
+            }
+
+            for n in 0..10 {
+                sender.send(n).unwrap();
+            }
+            drop(sender);
+        });
+    }
+
+    #[test]
+    fn test_lagged_receiver() {
+        let (sender, mut receiver) = channel(2);
+        for n in 0..5 {
+            sender.send(n).unwrap();
+        }
+
+        assert_eq!(receiver.recv(), Err(RecvError::Lagged(3)));
+        assert_eq!(receiver.recv(), Ok(3));
+        assert_eq!(receiver.recv(), Ok(4));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_subscribe_sees_only_new_messages() {
+        let (sender, mut first) = channel(4);
+        sender.send("before").unwrap();
+
+        let mut second = sender.subscribe();
+        sender.send("after").unwrap();
+
+        assert_eq!(first.try_recv(), Ok("before"));
+        assert_eq!(first.try_recv(), Ok("after"));
+        assert_eq!(second.try_recv(), Ok("after"));
+        assert_eq!(second.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_closed() {
+        let (sender, mut receiver) = channel(4);
+        let other = sender.clone();
+        sender.send(1).unwrap();
+        drop(sender);
+
+        // still open while a clone is alive
+        assert_eq!(receiver.try_recv(), Ok(1));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+        other.send(2).unwrap();
+        drop(other);
+
+        // buffered messages are still delivered after the last sender is gone
+        assert_eq!(receiver.recv(), Ok(2));
+        assert_eq!(receiver.recv(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn test_send_without_receivers() {
+        let (sender, receiver) = channel(4);
+        assert_eq!(sender.receiver_count(), 1);
+
+        drop(receiver);
+        assert_eq!(sender.receiver_count(), 0);
+        assert_eq!(sender.send(1), Err(SendError(1)));
+    }
+
+    #[test]
+    fn test_select_wakers_bounded() {
+        let (sender, mut receiver) = channel::<i32>(4);
+
+        // nothing is sent, so the wakers would pile up if every select added its own
+        for _ in 0..100 {
+            let timed_out = select! {
+                recv(&mut receiver) -> _ => false,
+                timeout(Duration::ZERO) => true,
+            };
+            assert!(timed_out);
+        }
+        assert_eq!(sender.shared.state.lock().unwrap().wakers.len(), 1);
+    }
+}
@@ -1,43 +1,214 @@
+mod allocator;
+mod broadcast;
+mod executor;
+mod metrics;
+mod select;
+mod state_channel;
+
 use std::{
-    cell::UnsafeCell, collections::VecDeque, marker::PhantomData, mem::MaybeUninit, sync::{
-        atomic::{AtomicBool, AtomicU8, Ordering},
-        Arc, Condvar, Mutex,
-    }, thread::{self, Thread}
+    cell::UnsafeCell, collections::VecDeque, future::Future, marker::PhantomData, mem::MaybeUninit, pin::Pin, sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        mpsc::{RecvError, RecvTimeoutError},
+        Condvar, Mutex,
+    }, task::{Context, Poll, Waker}, thread::{self, Thread}, time::{Duration, Instant}
 };
 
+use allocator::Shared;
+use allocator_api::{Allocator, Global};
+use metrics::{Metrics, MetricsSnapshot};
+use select::{SelectSend, Selectable};
+use state_channel::StateOneShotChannel;
+
 fn main() {
     println!("Hello, world!");
     test_one_shot_channel();
     test_compile_check_chanel();
     test_channel_ref();
+    test_blocking_channel();
+    test_async_channel();
+    test_select();
 }
 
 struct Channel<T> {
     queue: Mutex<VecDeque<T>>,
     is_ready: Condvar,
+    // registered by `select` while the queue lock is held
+    wakers: Mutex<Vec<Waker>>,
+    // threads blocked in `receive`
+    waiting: AtomicUsize,
+    // live ChannelSender and ChannelReceiver handles
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    metrics: Option<Metrics>,
 }
 
+#[allow(dead_code)]
 impl<T> Channel<T> {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Channel {
             queue: Mutex::new(VecDeque::new()),
             is_ready: Condvar::new(),
+            wakers: Mutex::new(Vec::new()),
+            waiting: AtomicUsize::new(0),
+            senders: AtomicUsize::new(0),
+            receivers: AtomicUsize::new(0),
+            metrics: None,
+        }
+    }
+
+    // also keeps counters, which costs a clock read on every send and receive
+    pub fn with_metrics() -> Self {
+        Channel {
+            metrics: Some(Metrics::default()),
+            ..Channel::new()
         }
     }
 
     pub fn send(&self, value: T) {
-        self.queue.lock().unwrap().push_back(value);
+        let start = self.metrics.as_ref().map(|_| Instant::now());
+        let mut queue = self.queue.lock().unwrap();
+        queue.push_back(value);
+        if let (Some(metrics), Some(start)) = (&self.metrics, start) {
+            metrics.record_send(queue.len(), start.elapsed());
+        }
+        drop(queue);
+
         self.is_ready.notify_one();
+        for waker in self.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
     }
 
     pub fn receive(&self) -> T {
+        let start = self.metrics.as_ref().map(|_| Instant::now());
         let mut b = self.queue.lock().unwrap();
         loop {
             if let Some(value) = b.pop_front() {
+                if let (Some(metrics), Some(start)) = (&self.metrics, start) {
+                    metrics.record_receive(start.elapsed());
+                }
                 return value;
             }
+            self.waiting.fetch_add(1, Ordering::Relaxed);
             b = self.is_ready.wait(b).unwrap();
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap().is_empty()
+    }
+
+    // the queue is unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        None
+    }
+
+    // threads blocked in `receive`, a select only leaves a waker behind
+    // that stays registered after it moved on, so it can't be counted
+    pub fn waiting_receivers(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    // handles are counted, sending and receiving through `&Channel` isn't
+    pub fn sender(&self) -> ChannelSender<'_, T> {
+        self.senders.fetch_add(1, Ordering::Relaxed);
+        ChannelSender { channel: self }
+    }
+
+    pub fn receiver(&self) -> ChannelReceiver<'_, T> {
+        self.receivers.fetch_add(1, Ordering::Relaxed);
+        ChannelReceiver { channel: self }
+    }
+
+    pub fn sender_count(&self) -> usize {
+        self.senders.load(Ordering::Relaxed)
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.receivers.load(Ordering::Relaxed)
+    }
+
+    pub fn metrics(&self) -> Option<MetricsSnapshot> {
+        self.metrics.as_ref().map(Metrics::snapshot)
+    }
+}
+
+pub struct ChannelSender<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+impl<T> ChannelSender<'_, T> {
+    pub fn send(&self, value: T) {
+        self.channel.send(value);
+    }
+}
+
+impl<T> Clone for ChannelSender<'_, T> {
+    fn clone(&self) -> Self {
+        self.channel.sender()
+    }
+}
+
+impl<T> Drop for ChannelSender<'_, T> {
+    fn drop(&mut self) {
+        self.channel.senders.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct ChannelReceiver<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+impl<T> ChannelReceiver<'_, T> {
+    pub fn receive(&self) -> T {
+        self.channel.receive()
+    }
+}
+
+impl<T> Clone for ChannelReceiver<'_, T> {
+    fn clone(&self) -> Self {
+        self.channel.receiver()
+    }
+}
+
+impl<T> Drop for ChannelReceiver<'_, T> {
+    fn drop(&mut self) {
+        self.channel.receivers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T> Selectable for &Channel<T> {
+    type Output = T;
+
+    fn poll_select(&mut self, waker: &Waker) -> Poll<T> {
+        let mut queue = self.queue.lock().unwrap();
+        if let Some(value) = queue.pop_front() {
+            // time spent in the select itself isn't visible from here
+            if let Some(metrics) = &self.metrics {
+                metrics.record_receive(Duration::ZERO);
+            }
+            return Poll::Ready(value);
+        }
+        // still holding the queue lock, so a send can't slip in before we are registered
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
         }
+        Poll::Pending
+    }
+}
+
+impl<T> SelectSend<T> for &Channel<T> {
+    type Output = ();
+
+    fn poll_send(&mut self, value: T, _waker: &Waker) -> Result<(), T> {
+        self.send(value);
+        Ok(())
     }
 }
 
@@ -49,6 +220,7 @@ struct OneShotChannel<T> {
 
 unsafe impl<T> Sync for OneShotChannel<T> where T: Send {}
 
+#[allow(dead_code)]
 impl<T> OneShotChannel<T> {
     pub fn new() -> Self {
         OneShotChannel {
@@ -103,82 +275,62 @@ fn test_one_shot_channel() {
     });
 }
 
-const EMPTY: u8 = 0;
-const WRITING: u8 = 1;
-const READING: u8 = 2;
-const READY: u8 = 2;
-
-struct StateOneShotChannel<T> {
-    value: UnsafeCell<MaybeUninit<T>>,
-    state: AtomicU8,
-}
-
-unsafe impl<T: Send> Sync for StateOneShotChannel<T> {}
-
-impl<T> StateOneShotChannel<T> {
-    fn new() -> Self {
-        StateOneShotChannel {
-            value: UnsafeCell::new(MaybeUninit::uninit()),
-            state: AtomicU8::new(EMPTY),
-        }
-    }
-
-    pub fn send(&self, value: T) {
-        if self
-            .state
-            .compare_exchange(EMPTY, WRITING, Ordering::Relaxed, Ordering::Relaxed)
-            .is_err()
-        {
-            panic!("Can't send more than one message");
-        }
-        unsafe { (*self.value.get()).write(value) };
-        self.state.store(READY, Ordering::Release);
-    }
+const OPEN: u8 = 0;
+const WAITING: u8 = 1;
+const SENT: u8 = 2;
+const CLOSED: u8 = 3;
 
-    pub fn is_ready(&self) -> bool {
-        self.state.load(Ordering::Relaxed) == READY
-    }
+#[derive(Debug, PartialEq, Eq)]
+pub struct Canceled;
 
-    pub fn receive(&self) -> T {
-        if self
-            .state
-            .compare_exchange(READY, READING, Ordering::Acquire, Ordering::Relaxed)
-            .is_err()
-        {
-            panic!("Message is not ready");
-        }
-        unsafe { (*self.value.get()).assume_init_read() }
-    }
+// whoever has to be woken up once the message is sent or the sender is dropped
+enum Waiter {
+    Thread(Thread),
+    Task(Waker),
 }
 
-impl<T> Drop for StateOneShotChannel<T> {
-    fn drop(&mut self) {
-        if *self.state.get_mut() == READY {
-            unsafe { (*self.value.get_mut()).assume_init_drop() };
+impl Waiter {
+    fn wake(&self) {
+        match self {
+            Waiter::Thread(thread) => thread.unpark(),
+            Waiter::Task(waker) => waker.wake_by_ref(),
         }
     }
 }
 
 pub struct Channel1<T> {
     message: UnsafeCell<MaybeUninit<T>>,
-    ready: AtomicBool,
+    // OPEN -> WAITING when the receiver parks, SENT once a message is written
+    // and CLOSED when it has been received or the sender went away
+    state: AtomicU8,
+    // only written by the receiver while the state is OPEN
+    waiter: UnsafeCell<Option<Waiter>>,
 }
 
 unsafe impl<T: Send> Sync for Channel1<T> {}
 
-pub struct Sender<T> {
-    channel: Arc<Channel1<T>>,
+pub struct Sender<T, A: Allocator = Global> {
+    channel: Shared<Channel1<T>, A>,
 }
 
-struct Receiver<T> {
-    channel: Arc<Channel1<T>>,
+struct Receiver<T, A: Allocator = Global> {
+    channel: Shared<Channel1<T>, A>,
 }
 
 fn channel<T>() -> (Sender<T>, Receiver<T>) {
-    let ch = Arc::new(Channel1 {
-        message: UnsafeCell::new(MaybeUninit::uninit()),
-        ready: AtomicBool::new(false),
-    });
+    channel_in(Global)
+}
+
+// like channel, with the shared state allocated by `alloc`
+fn channel_in<T, A: Allocator + Clone>(alloc: A) -> (Sender<T, A>, Receiver<T, A>) {
+    let ch = Shared::new_in(
+        Channel1 {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            state: AtomicU8::new(OPEN),
+            waiter: UnsafeCell::new(None),
+        },
+        alloc,
+    );
 
     (
         Sender {
@@ -188,29 +340,149 @@ fn channel<T>() -> (Sender<T>, Receiver<T>) {
     )
 }
 
-impl<T> Sender<T> {
+impl<T, A: Allocator> Sender<T, A> {
     pub fn send(self, value: T) {
         unsafe { (*self.channel.message.get()).write(value) };
-        self.channel.ready.store(true, Ordering::Release);
+        if self.channel.state.swap(SENT, Ordering::AcqRel) == WAITING {
+            unsafe { (*self.channel.waiter.get()).as_ref().unwrap().wake() };
+        }
+    }
+}
+
+impl<T, A: Allocator> Drop for Sender<T, A> {
+    fn drop(&mut self) {
+        // a sender that goes away without sending closes the channel,
+        // after a send the state is already SENT or CLOSED and this is a no-op
+        if let Ok(WAITING) = self.channel.state.fetch_update(
+            Ordering::Acquire,
+            Ordering::Relaxed,
+            |s| (s == OPEN || s == WAITING).then_some(CLOSED),
+        ) {
+            unsafe { (*self.channel.waiter.get()).as_ref().unwrap().wake() };
+        }
     }
 }
 
-impl<T> Receiver<T> {
+#[allow(dead_code)]
+impl<T, A: Allocator> Receiver<T, A> {
     pub fn is_ready(&self) -> bool {
-        self.channel.ready.load(Ordering::Relaxed)
+        self.channel.state.load(Ordering::Relaxed) == SENT
     }
 
-    pub fn receive(self) -> T {
-        if !self.channel.ready.swap(false, Ordering::Acquire) {
-            panic!("Message is not available");
+    // fails if the sender went away without sending, like std's recv
+    pub fn receive(self) -> Result<T, RecvError> {
+        self.receive_until(None).map_err(|_| RecvError)
+    }
+
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.receive_until(Some(Instant::now() + timeout))
+    }
+
+    fn receive_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut s = self.register(Waiter::Thread(thread::current()));
+
+        loop {
+            match s {
+                SENT => return Ok(self.take()),
+                CLOSED => return Err(RecvTimeoutError::Disconnected),
+                _ => {}
+            }
+
+            match deadline {
+                None => thread::park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        // stop waiting so the next call can register a different thread
+                        return match self.channel.state.compare_exchange(
+                            WAITING,
+                            OPEN,
+                            Ordering::Relaxed,
+                            Ordering::Acquire,
+                        ) {
+                            Ok(_) => Err(RecvTimeoutError::Timeout),
+                            Err(SENT) => Ok(self.take()),
+                            Err(_) => Err(RecvTimeoutError::Disconnected),
+                        };
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+            }
+            s = self.channel.state.load(Ordering::Acquire);
+        }
+    }
+
+    // makes `waiter` the one to wake up, replacing an earlier registration, and returns the new state
+    fn register(&self, waiter: Waiter) -> u8 {
+        let mut s = self.channel.state.load(Ordering::Acquire);
+        if s == WAITING {
+            // take back the registration first, the sender may be reading it
+            s = match self.channel.state.compare_exchange(
+                WAITING,
+                OPEN,
+                Ordering::Relaxed,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => OPEN,
+                Err(e) => e,
+            };
+        }
+
+        if s == OPEN {
+            // the sender only looks at the waiter after it sees WAITING
+            unsafe { *self.channel.waiter.get() = Some(waiter) };
+            s = match self.channel.state.compare_exchange(
+                OPEN,
+                WAITING,
+                Ordering::Release,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => WAITING,
+                Err(e) => e,
+            };
         }
+        s
+    }
+
+    fn take(&self) -> T {
+        self.channel.state.store(CLOSED, Ordering::Relaxed);
         unsafe { (*self.channel.message.get()).assume_init_read() }
     }
 }
 
+impl<T, A: Allocator> Future for Receiver<T, A> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let s = self.channel.state.load(Ordering::Acquire);
+        if s == WAITING {
+            // the sender may be reading the waiter as well, which is fine as long as we don't write
+            if let Some(Waiter::Task(waker)) = unsafe { &*self.channel.waiter.get() } {
+                if waker.will_wake(cx.waker()) {
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        match self.register(Waiter::Task(cx.waker().clone())) {
+            SENT => Poll::Ready(Ok(self.take())),
+            CLOSED => Poll::Ready(Err(Canceled)),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl<T, A: Allocator> Selectable for &mut Receiver<T, A> {
+    type Output = Result<T, Canceled>;
+
+    fn poll_select(&mut self, waker: &Waker) -> Poll<Self::Output> {
+        Pin::new(&mut **self).poll(&mut Context::from_waker(waker))
+    }
+}
+
 impl<T> Drop for Channel1<T> {
     fn drop(&mut self) {
-        if *self.ready.get_mut() {
+        if *self.state.get_mut() == SENT {
             unsafe { self.message.get_mut().assume_init_drop() };
         }
     }
@@ -229,26 +501,97 @@ pub fn test_compile_check_chanel() {
         while !receiver.is_ready() {
             thread::park();
         }
-        assert!(receiver.receive() == "hello cats");
+        assert!(receiver.receive() == Ok("hello cats"));
+    });
+}
+
+fn test_blocking_channel() {
+    let (sender, receiver) = channel();
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            sender.send("hello cats");
+        });
+
+        assert!(receiver.receive() == Ok("hello cats"));
+    });
+}
+
+fn test_async_channel() {
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        sender.send("hello async cats");
+    });
+
+    let message = executor::block_on(async { receiver.await.unwrap() });
+    assert_eq!(message, "hello async cats");
+}
+
+fn test_select() {
+    let (sender, mut receiver) = channel::<&str>();
+    let queue = Channel::new();
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            queue.send("hello queue");
+        });
+
+        select::select! {
+            recv(&mut receiver) -> _ => panic!("nothing was sent on the one shot channel"),
+            recv(&queue) -> message => assert_eq!(message, "hello queue"),
+            timeout(Duration::from_secs(5)) => panic!("timed out"),
+        }
+        drop(sender);
     });
 }
 
 pub struct Channel2<T> {
     message: UnsafeCell<MaybeUninit<T>>,
-    ready: AtomicBool,
+    state: AtomicU8,
+    // only written by the receiver while the state is OPEN
+    receiving_thread: UnsafeCell<Option<Thread>>,
+    // SENDER_ALIVE | RECEIVER_ALIVE, the channel can only be split again once both are gone
+    in_use: AtomicU8,
 }
 
+const SENDER_ALIVE: u8 = 1;
+const RECEIVER_ALIVE: u8 = 2;
+
 impl<T> Channel2<T> {
     pub fn channel() -> Self {
         Channel2 {
             message: UnsafeCell::new(MaybeUninit::uninit()),
-            ready: AtomicBool::new(false),
+            state: AtomicU8::new(OPEN),
+            receiving_thread: UnsafeCell::new(None),
+            in_use: AtomicU8::new(0),
         }
     }
 
-    pub fn split<'a>(&'a mut self) -> (Sender2<'a, T>, Receiver2<'a, T>) {
-        *self = Channel2::channel();
-        (Sender2 { channel: self, thread: thread::current() }, Receiver2 { channel: self, _no_send: PhantomData } )
+    pub fn split(&self) -> (Sender2<'_, T>, Receiver2<'_, T>) {
+        loop {
+            match self.in_use.compare_exchange(
+                0,
+                SENDER_ALIVE | RECEIVER_ALIVE,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                // the previous sender is done with the message and only has to finish waking us up
+                Err(SENDER_ALIVE) if matches!(self.state.load(Ordering::Relaxed), SENT | CLOSED) => {
+                    std::hint::spin_loop()
+                }
+                Err(_) => panic!("Channel is still in use by a previous split"),
+            }
+        }
+        // both halves of the previous round are gone, so nobody else touches the message
+        if self.state.swap(OPEN, Ordering::Relaxed) == SENT {
+            unsafe { (*self.message.get()).assume_init_drop() };
+        }
+        (Sender2 { channel: self }, Receiver2 { channel: self })
     }
 }
 
@@ -256,38 +599,79 @@ unsafe impl<T: Send> Sync for Channel2<T> {}
 
 pub struct Receiver2<'a, T> {
     channel: &'a Channel2<T>,
-    _no_send: PhantomData<*const ()>
 }
 
 impl<T> Receiver2<'_, T> {
     pub fn is_ready(&self) -> bool {
-        self.channel.ready.load(Ordering::Relaxed)
+        self.channel.state.load(Ordering::Relaxed) == SENT
     }
 
-    pub fn receive(&self) -> T {
-        while !self.channel.ready.swap(false, Ordering::Acquire) {
-            thread::park();
+    pub fn receive(self) -> T {
+        let mut s = self.channel.state.load(Ordering::Acquire);
+        if s == OPEN {
+            // register whichever thread is receiving this round
+            unsafe { *self.channel.receiving_thread.get() = Some(thread::current()) };
+            s = match self.channel.state.compare_exchange(
+                OPEN,
+                WAITING,
+                Ordering::Release,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => WAITING,
+                Err(e) => e,
+            };
         }
+
+        loop {
+            match s {
+                SENT => break,
+                CLOSED => panic!("Sender dropped without sending a message"),
+                _ => thread::park(),
+            }
+            s = self.channel.state.load(Ordering::Acquire);
+        }
+
+        self.channel.state.store(CLOSED, Ordering::Relaxed);
         unsafe { (*self.channel.message.get()).assume_init_read() }
     }
 }
 
+impl<T> Drop for Receiver2<'_, T> {
+    fn drop(&mut self) {
+        self.channel.in_use.fetch_and(!RECEIVER_ALIVE, Ordering::Release);
+    }
+}
+
 pub struct Sender2<'a, T> {
     channel: &'a Channel2<T>,
-    thread: Thread,
 }
 
 impl<T> Sender2<'_, T> {
-    pub fn send(&self, message: T) {
+    pub fn send(self, message: T) {
         unsafe { (*self.channel.message.get()).write(message) };
-        self.channel.ready.store(true, Ordering::Release);
-        self.thread.unpark();
+        if self.channel.state.swap(SENT, Ordering::AcqRel) == WAITING {
+            unsafe { (*self.channel.receiving_thread.get()).as_ref().unwrap().unpark() };
+        }
+    }
+}
+
+impl<T> Drop for Sender2<'_, T> {
+    fn drop(&mut self) {
+        // a sender that goes away without sending closes the channel for this round
+        if let Ok(WAITING) = self.channel.state.fetch_update(
+            Ordering::Acquire,
+            Ordering::Relaxed,
+            |s| (s == OPEN || s == WAITING).then_some(CLOSED),
+        ) {
+            unsafe { (*self.channel.receiving_thread.get()).as_ref().unwrap().unpark() };
+        }
+        self.channel.in_use.fetch_and(!SENDER_ALIVE, Ordering::Release);
     }
 }
 
 impl<T> Drop for Channel2<T> {
     fn drop(&mut self) {
-        if *self.ready.get_mut() {
+        if *self.state.get_mut() == SENT {
            unsafe { (*self.message.get_mut()).assume_init_drop() };
         }
     }
@@ -295,7 +679,7 @@ impl<T> Drop for Channel2<T> {
 
 fn test_channel_ref() {
 
-    let mut channel = Channel2::channel();
+    let channel = Channel2::channel();
     let (sender, receiver) = channel.split();
 
     thread::scope(|s| {
@@ -309,8 +693,269 @@ fn test_channel_ref() {
 
 }
 
+#[allow(dead_code)]
 struct Iter<'a, T: 'a> {
     ptr: *const T,
     end: *const T,
     _marker: PhantomData<&'a T>
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+
+    // hands out memory from the global allocator and keeps track of what is still live
+    struct CountingAlloc {
+        live: AtomicUsize,
+    }
+
+    unsafe impl Allocator for CountingAlloc {
+        fn allocate(&self, layout: Layout) -> *mut u8 {
+            self.live.fetch_add(layout.size(), Ordering::Relaxed);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+            self.live.fetch_sub(layout.size(), Ordering::Relaxed);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_channel_in() {
+        let alloc = CountingAlloc {
+            live: AtomicUsize::new(0),
+        };
+
+        let (sender, receiver) = channel_in(&alloc);
+        assert!(alloc.live.load(Ordering::Relaxed) > 0);
+        thread::scope(|s| {
+            s.spawn(|| sender.send(String::from("hello")));
+        });
+        assert_eq!(receiver.receive().as_deref(), Ok("hello"));
+        assert_eq!(alloc.live.load(Ordering::Relaxed), 0);
+
+        // the message is dropped with the channel when it was never received
+        let (sender, receiver) = channel_in(&alloc);
+        sender.send(vec![1, 2, 3]);
+        drop(receiver);
+        assert_eq!(alloc.live.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_recv_timeout() {
+        let (sender, mut receiver) = channel();
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                sender.send(42);
+            });
+
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(42));
+        });
+
+        // the message can only be received once
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn test_sender_dropped() {
+        let (sender, mut receiver) = channel::<i32>();
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                drop(sender);
+            });
+
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(5)),
+                Err(RecvTimeoutError::Disconnected)
+            );
+        });
+
+        // a blocking receive reports it too instead of panicking
+        let (sender, receiver) = channel::<i32>();
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                drop(sender);
+            });
+
+            assert_eq!(receiver.receive(), Err(RecvError));
+        });
+    }
+
+    #[test]
+    fn test_async_receiver() {
+        let (sender, receiver) = channel();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            sender.send(String::from("hello"));
+        });
+
+        assert_eq!(executor::block_on(receiver), Ok(String::from("hello")));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_receiver_canceled() {
+        let (sender, receiver) = channel::<i32>();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(sender);
+        });
+
+        assert_eq!(executor::block_on(receiver), Err(Canceled));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_receiver_new_waker() {
+        let (sender, mut receiver) = channel();
+
+        // register one waker and then move on to another one
+        assert!(executor::poll_once(&mut receiver).is_pending());
+        sender.send(7);
+
+        assert_eq!(executor::block_on(receiver), Ok(7));
+    }
+
+    #[test]
+    fn test_channel_introspection() {
+        let ch = Channel::new();
+        assert!(ch.is_empty());
+        assert_eq!(ch.capacity(), None);
+        assert_eq!(ch.metrics(), None);
+
+        ch.send(1);
+        ch.send(2);
+        assert_eq!(ch.len(), 2);
+        assert!(!ch.is_empty());
+
+        ch.receive();
+        assert_eq!(ch.len(), 1);
+    }
+
+    #[test]
+    fn test_channel_counts() {
+        let ch = Channel::new();
+        assert_eq!((ch.sender_count(), ch.receiver_count()), (0, 0));
+
+        let sender = ch.sender();
+        let receiver = ch.receiver();
+        let other = sender.clone();
+        assert_eq!((ch.sender_count(), ch.receiver_count()), (2, 1));
+
+        sender.send(1);
+        other.send(2);
+        drop((sender, other));
+        assert_eq!((ch.sender_count(), ch.receiver_count()), (0, 1));
+        assert_eq!(receiver.receive(), 1);
+
+        // a select that timed out isn't waiting anymore, even though its waker is still registered
+        ch.receive();
+        let timed_out = select::select! {
+            recv(&ch) -> _ => false,
+            timeout(Duration::ZERO) => true,
+        };
+        assert!(timed_out);
+        assert_eq!(ch.waiting_receivers(), 0);
+
+        thread::scope(|s| {
+            s.spawn(|| receiver.receive());
+            while ch.waiting_receivers() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            ch.send(3);
+        });
+        assert_eq!(ch.waiting_receivers(), 0);
+        drop(receiver);
+        assert_eq!(ch.receiver_count(), 0);
+    }
+
+    #[test]
+    fn test_channel_metrics() {
+        let ch = Channel::with_metrics();
+        for n in 0..3 {
+            ch.send(n);
+        }
+        ch.receive();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                ch.receive();
+                ch.receive();
+                // this one has to wait for the send below
+                ch.receive();
+            });
+
+            while ch.waiting_receivers() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread::sleep(Duration::from_millis(20));
+            ch.send(3);
+        });
+
+        let metrics = ch.metrics().unwrap();
+        assert_eq!(metrics.sent, 4);
+        assert_eq!(metrics.received, 4);
+        assert_eq!(metrics.peak_depth, 3);
+        assert!(metrics.recv_blocked >= Duration::from_millis(20));
+        assert_eq!(ch.waiting_receivers(), 0);
+    }
+
+    #[test]
+    fn test_channel_ref_reuse() {
+        let channel = Channel2::channel();
+        let (requests, work) = std::sync::mpsc::channel::<(u32, Sender2<u32>)>();
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                for (n, reply) in work {
+                    reply.send(n * 2);
+                }
+            });
+
+            for n in 0..100 {
+                let (sender, receiver) = channel.split();
+                requests.send((n, sender)).unwrap();
+                assert_eq!(receiver.receive(), n * 2);
+            }
+            drop(requests);
+        });
+    }
+
+    #[test]
+    fn test_channel_ref_unreceived_message() {
+        let channel = Channel2::channel();
+
+        let (sender, receiver) = channel.split();
+        sender.send(String::from("dropped on the next split"));
+        drop(receiver);
+
+        let (sender, receiver) = channel.split();
+        thread::scope(|s| {
+            s.spawn(move || sender.send(String::from("hello")));
+            assert_eq!(receiver.receive(), "hello");
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "still in use")]
+    fn test_channel_ref_split_while_in_use() {
+        let channel = Channel2::<i32>::channel();
+        let (_sender, _receiver) = channel.split();
+        let _ = channel.split();
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+// counters a channel keeps when created with metrics enabled
+#[derive(Default)]
+pub struct Metrics {
+    sent: AtomicU64,
+    received: AtomicU64,
+    peak_depth: AtomicUsize,
+    send_blocked_nanos: AtomicU64,
+    recv_blocked_nanos: AtomicU64,
+}
+
+// point in time copy of the counters, cheap to hand to a monitoring exporter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MetricsSnapshot {
+    pub sent: u64,
+    pub received: u64,
+    pub peak_depth: usize,
+    pub send_blocked: Duration,
+    pub recv_blocked: Duration,
+}
+
+#[allow(dead_code)]
+impl Metrics {
+    // depth is the queue length right after the send
+    pub fn record_send(&self, depth: usize, blocked: Duration) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.peak_depth.fetch_max(depth, Ordering::Relaxed);
+        self.send_blocked_nanos
+            .fetch_add(blocked.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_receive(&self, blocked: Duration) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.recv_blocked_nanos
+            .fetch_add(blocked.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // the counters are independent, so a snapshot taken during traffic
+    // may be off by the operations in flight
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            sent: self.sent.load(Ordering::Relaxed),
+            received: self.received.load(Ordering::Relaxed),
+            peak_depth: self.peak_depth.load(Ordering::Relaxed),
+            send_blocked: Duration::from_nanos(self.send_blocked_nanos.load(Ordering::Relaxed)),
+            recv_blocked: Duration::from_nanos(self.recv_blocked_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
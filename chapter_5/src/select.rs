@@ -0,0 +1,280 @@
+use std::{
+    task::{Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::executor;
+
+// a channel operation that can take part in a select
+pub trait Selectable {
+    type Output;
+
+    // completes the operation if it can proceed without blocking, otherwise
+    // registers `waker` to be woken once that might have changed
+    fn poll_select(&mut self, waker: &Waker) -> Poll<Self::Output>;
+}
+
+// the sending side of a select, hands the value back while the operation can't proceed
+pub trait SelectSend<T> {
+    type Output;
+
+    fn poll_send(&mut self, value: T, waker: &Waker) -> Result<Self::Output, T>;
+}
+
+// stores the output in the caller's slot and returns true once the operation is done
+type Arm<'a> = Box<dyn FnMut(&Waker) -> bool + 'a>;
+
+// blocks until one of the registered operations completes, arms are tried in
+// order so earlier ones win when several are ready at the same time
+pub struct Select<'a> {
+    arms: Vec<Arm<'a>>,
+    deadline: Option<Instant>,
+}
+
+#[allow(dead_code)]
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select {
+            arms: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    pub fn recv<S: Selectable + 'a>(mut self, mut source: S, slot: &'a mut Option<S::Output>) -> Self {
+        self.arms.push(Box::new(move |waker| match source.poll_select(waker) {
+            Poll::Ready(output) => {
+                *slot = Some(output);
+                true
+            }
+            Poll::Pending => false,
+        }));
+        self
+    }
+
+    pub fn send<T: 'a, S: SelectSend<T> + 'a>(
+        mut self,
+        mut sink: S,
+        value: T,
+        slot: &'a mut Option<S::Output>,
+    ) -> Self {
+        let mut value = Some(value);
+        self.arms.push(Box::new(move |waker| {
+            match sink.poll_send(value.take().unwrap(), waker) {
+                Ok(output) => {
+                    *slot = Some(output);
+                    true
+                }
+                Err(v) => {
+                    value = Some(v);
+                    false
+                }
+            }
+        }));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    // returns the index of the completed operation or None if the timeout expired
+    pub fn wait(mut self) -> Option<usize> {
+        // one token shared by every channel we are waiting on
+        let waker = executor::thread_waker();
+        loop {
+            for (i, arm) in self.arms.iter_mut().enumerate() {
+                if arm(&waker) {
+                    return Some(i);
+                }
+            }
+
+            match self.deadline {
+                None => thread::park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+            }
+        }
+    }
+}
+
+// select! {
+//     recv(&mut receiver) -> message => ...,
+//     send(&sender, value) -> result => ...,
+//     timeout(Duration::from_secs(1)) => ...,
+// }
+macro_rules! select {
+    (@munch [$($ops:tt)*] [$($arms:tt)*] [$($timeout:tt)*]
+        recv($source:expr) -> $p:pat => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select::select!(@munch
+            [$($ops)* .recv($source, &mut slot)]
+            [$($arms)* (slot, $p, $body)]
+            [$($timeout)*]
+            $($($rest)*)?)
+    }};
+    (@munch [$($ops:tt)*] [$($arms:tt)*] [$($timeout:tt)*]
+        send($sink:expr, $value:expr) -> $p:pat => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut slot = None;
+        $crate::select::select!(@munch
+            [$($ops)* .send($sink, $value, &mut slot)]
+            [$($arms)* (slot, $p, $body)]
+            [$($timeout)*]
+            $($($rest)*)?)
+    }};
+    (@munch [$($ops:tt)*] [$($arms:tt)*] []
+        timeout($timeout:expr) => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::select::select!(@munch
+            [$($ops)* .timeout($timeout)]
+            [$($arms)*]
+            [$body]
+            $($($rest)*)?)
+    };
+    (@munch [$($ops:tt)*] [$(($slot:ident, $p:pat, $body:expr))*] [$($timeout:expr)?]) => {{
+        $crate::select::Select::new() $($ops)* .wait();
+        $(if let Some($p) = $slot { $body } else)* {
+            $crate::select::select!(@timeout $($timeout)?)
+        }
+    }};
+    (@timeout) => { unreachable!("select without a timeout always completes an operation") };
+    (@timeout $body:expr) => { $body };
+    ($($arms:tt)+) => {
+        $crate::select::select!(@munch [] [] [] $($arms)+)
+    };
+}
+
+pub(crate) use select;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{broadcast, channel, Canceled, Channel};
+    use std::thread;
+
+    #[test]
+    fn test_select_recv() {
+        let (sender, mut receiver) = channel();
+        let (broadcast_sender, mut broadcast_receiver) = broadcast::channel::<i32>(4);
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                sender.send("one shot");
+            });
+
+            let message = select! {
+                recv(&mut broadcast_receiver) -> _ => panic!("nothing was broadcast"),
+                recv(&mut receiver) -> message => message,
+            };
+            assert_eq!(message, Ok("one shot"));
+
+            // the one shot channel is used up now
+            let message = select! {
+                recv(&mut receiver) -> message => message.map(|_| 0),
+                recv(&mut broadcast_receiver) -> message => Ok(message.unwrap()),
+            };
+            assert_eq!(message, Err(Canceled));
+
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                broadcast_sender.send(5).unwrap();
+            });
+            let message = select! {
+                recv(&mut broadcast_receiver) -> message => message,
+                timeout(Duration::from_secs(5)) => panic!("timed out"),
+            };
+            assert_eq!(message, Ok(5));
+        });
+    }
+
+    #[test]
+    fn test_select_many_messages() {
+        let first = Channel::new();
+        let second = Channel::new();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for n in 0..100 {
+                    if n % 2 == 0 {
+                        first.send(n);
+                    } else {
+                        second.send(n);
+                    }
+                }
+            });
+
+            let mut received = Vec::new();
+            while received.len() < 100 {
+                select! {
+                    recv(&first) -> n => received.push(n),
+                    recv(&second) -> n => received.push(n),
+                }
+            }
+            received.sort();
+            assert_eq!(received, (0..100).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn test_select_send_and_timeout() {
+        let queue = Channel::new();
+        let (_sender, mut receiver) = channel::<i32>();
+
+        let sent = select! {
+            recv(&mut receiver) -> _ => false,
+            send(&queue, 1) -> _ => true,
+        };
+        assert!(sent);
+        assert_eq!(queue.receive(), 1);
+
+        let start = Instant::now();
+        let timed_out = select! {
+            recv(&mut receiver) -> _ => false,
+            recv(&queue) -> _ => false,
+            timeout(Duration::from_millis(50)) => true,
+        };
+        assert!(timed_out);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_select_builder() {
+        let first = Channel::<&str>::new();
+        let second = Channel::new();
+        second.send("second");
+
+        let (mut a, mut b) = (None, None);
+        let selected = Select::new()
+            .recv(&first, &mut a)
+            .recv(&second, &mut b)
+            .wait();
+
+        assert_eq!(selected, Some(1));
+        assert_eq!((a, b), (None, Some("second")));
+    }
+
+    #[test]
+    fn test_select_loop_wakers_bounded() {
+        let first = Channel::<i32>::new();
+        let second = Channel::<i32>::new();
+
+        // nothing ever arrives, every round leaves its waker on the channels that lost
+        for _ in 0..100 {
+            let timed_out = select! {
+                recv(&first) -> _ => false,
+                recv(&second) -> _ => false,
+                timeout(Duration::ZERO) => true,
+            };
+            assert!(timed_out);
+        }
+        // but it's the same waker every time, so each channel holds it once
+        assert_eq!(first.wakers.lock().unwrap().len(), 1);
+        assert_eq!(second.wakers.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,336 @@
+use std::mem::MaybeUninit;
+
+use sync::{AtomicU8, Ordering, UnsafeCell};
+
+// the model tests swap in loom's types so every access is checked
+#[cfg(loom)]
+mod sync {
+    pub use loom::cell::UnsafeCell;
+    pub use loom::sync::atomic::{AtomicU8, Ordering};
+}
+
+#[cfg(not(loom))]
+mod sync {
+    pub use std::sync::atomic::{AtomicU8, Ordering};
+
+    // mirrors the closure based api of loom's UnsafeCell
+    pub struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+    impl<T> UnsafeCell<T> {
+        pub fn new(data: T) -> Self {
+            UnsafeCell(std::cell::UnsafeCell::new(data))
+        }
+
+        pub fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+            f(self.0.get())
+        }
+
+        pub fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+            f(self.0.get())
+        }
+    }
+}
+
+// nothing has been sent yet
+const EMPTY: u8 = 0;
+// the sender is writing the message
+const WRITING: u8 = 1;
+// the message is waiting to be received
+const READY: u8 = 2;
+// the receiver is moving the message out
+const READING: u8 = 3;
+// the message has been received
+const CONSUMED: u8 = 4;
+// closed before anything was sent
+const CLOSED: u8 = 5;
+
+pub struct StateOneShotChannel<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    state: AtomicU8,
+}
+
+unsafe impl<T: Send> Sync for StateOneShotChannel<T> {}
+
+#[allow(dead_code)]
+impl<T> StateOneShotChannel<T> {
+    pub fn new() -> Self {
+        StateOneShotChannel {
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            state: AtomicU8::new(EMPTY),
+        }
+    }
+
+    pub fn send(&self, value: T) {
+        if self.try_send(value).is_err() {
+            match self.state.load(Ordering::Relaxed) {
+                CLOSED => panic!("Can't send on a closed channel"),
+                _ => panic!("Can't send more than one message"),
+            }
+        }
+    }
+
+    // hands the value back if a message was already sent or the channel is closed
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        if self
+            .state
+            .compare_exchange(EMPTY, WRITING, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(value);
+        }
+        self.value.with_mut(|v| unsafe { (*v).write(value) });
+        self.state.store(READY, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == READY
+    }
+
+    // closes the channel if nothing has been sent, returns false if it was too late
+    pub fn close(&self) -> bool {
+        self.state
+            .compare_exchange(EMPTY, CLOSED, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn receive(&self) -> T {
+        match self.try_receive() {
+            Some(value) => value,
+            None => panic!("Message is not ready"),
+        }
+    }
+
+    pub fn try_receive(&self) -> Option<T> {
+        if self
+            .state
+            .compare_exchange(READY, READING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+        let value = self.value.with(|v| unsafe { (*v).assume_init_read() });
+        self.state.store(CONSUMED, Ordering::Relaxed);
+        Some(value)
+    }
+}
+
+impl<T> Drop for StateOneShotChannel<T> {
+    fn drop(&mut self) {
+        // &mut self means every send and receive has already finished
+        match self.state.load(Ordering::Relaxed) {
+            READY => self.value.with_mut(|v| unsafe { (*v).assume_init_drop() }),
+            EMPTY | CONSUMED | CLOSED => {}
+            s => unreachable!("channel dropped in the middle of a send or receive: {s}"),
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::{
+        rc::Rc,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    struct DropCounter<'a>(&'a AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_send_receive() {
+        let ch = StateOneShotChannel::new();
+        assert!(!ch.is_ready());
+        assert!(ch.try_receive().is_none());
+
+        ch.send(Rc::new(5));
+        assert!(ch.is_ready());
+
+        assert_eq!(*ch.receive(), 5);
+        assert!(!ch.is_ready());
+        assert!(ch.try_receive().is_none());
+        assert!(!ch.close());
+    }
+
+    #[test]
+    #[should_panic(expected = "more than one message")]
+    fn test_send_twice() {
+        let ch = StateOneShotChannel::new();
+        ch.send(1);
+        ch.send(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than one message")]
+    fn test_send_after_receive() {
+        let ch = StateOneShotChannel::new();
+        ch.send(1);
+        ch.receive();
+        ch.send(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "not ready")]
+    fn test_receive_twice() {
+        let ch = StateOneShotChannel::new();
+        ch.send(1);
+        ch.receive();
+        ch.receive();
+    }
+
+    #[test]
+    #[should_panic(expected = "closed channel")]
+    fn test_send_after_close() {
+        let ch = StateOneShotChannel::new();
+        assert!(ch.close());
+        ch.send(1);
+    }
+
+    #[test]
+    fn test_close() {
+        let ch = StateOneShotChannel::new();
+        assert!(ch.close());
+        assert!(!ch.close());
+        assert_eq!(ch.try_send(1), Err(1));
+        assert!(ch.try_receive().is_none());
+
+        let ch = StateOneShotChannel::new();
+        ch.send(1);
+        assert!(!ch.close());
+        assert_eq!(ch.receive(), 1);
+    }
+
+    #[test]
+    fn test_drop_exactly_once() {
+        let drops = AtomicUsize::new(0);
+
+        // never sent
+        drop(StateOneShotChannel::<DropCounter>::new());
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        // sent but never received, the channel owns the message
+        let ch = StateOneShotChannel::new();
+        ch.send(DropCounter(&drops));
+        drop(ch);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+
+        // received, the receiver owns the message
+        let ch = StateOneShotChannel::new();
+        ch.send(DropCounter(&drops));
+        let message = ch.receive();
+        drop(ch);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        drop(message);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+
+        // rejected by a closed channel, handed back to the sender
+        let ch = StateOneShotChannel::new();
+        ch.close();
+        let rejected = ch.try_send(DropCounter(&drops));
+        drop(ch);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        drop(rejected);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+}
+
+// run with: RUSTFLAGS="--cfg loom" cargo test --release loom
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn loom_send_receive() {
+        loom::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let ch = Arc::new(StateOneShotChannel::new());
+
+            let sender = {
+                let ch = ch.clone();
+                let drops = drops.clone();
+                thread::spawn(move || ch.send(DropCounter(drops)))
+            };
+
+            let received = ch.try_receive();
+            sender.join().unwrap();
+
+            let received = received.or_else(|| ch.try_receive());
+            assert!(received.is_some());
+            assert!(ch.try_receive().is_none());
+
+            drop(ch);
+            assert_eq!(drops.load(Ordering::Relaxed), 0);
+            drop(received);
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+
+    #[test]
+    fn loom_send_close() {
+        loom::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let ch = Arc::new(StateOneShotChannel::new());
+
+            let sender = {
+                let ch = ch.clone();
+                let drops = drops.clone();
+                thread::spawn(move || ch.try_send(DropCounter(drops)).is_ok())
+            };
+
+            let closed = ch.close();
+            let sent = sender.join().unwrap();
+
+            // exactly one of them wins and a rejected message is dropped by the sender
+            assert!(sent != closed);
+            assert_eq!(ch.is_ready(), sent);
+            assert_eq!(drops.load(Ordering::Relaxed), if sent { 0 } else { 1 });
+
+            drop(ch);
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+
+    #[test]
+    fn loom_send_receive_drop() {
+        loom::model(|| {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let ch = Arc::new(StateOneShotChannel::new());
+
+            // whichever thread finishes last drops the channel
+            let sender = {
+                let ch = ch.clone();
+                let drops = drops.clone();
+                thread::spawn(move || ch.send(DropCounter(drops)))
+            };
+            let receiver = {
+                let ch = ch.clone();
+                thread::spawn(move || drop(ch.try_receive()))
+            };
+            drop(ch);
+
+            sender.join().unwrap();
+            receiver.join().unwrap();
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+}
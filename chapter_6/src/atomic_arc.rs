@@ -0,0 +1,256 @@
+use crate::Arc;
+use std::{
+    hint,
+    mem::ManuallyDrop,
+    ptr,
+    sync::{
+        atomic::{fence, AtomicPtr, Ordering},
+        Mutex,
+    },
+};
+
+// an Arc that can be loaded and replaced atomically. the AtomicArc owns one
+// strong reference to the value it points at. a load has to read the pointer
+// and bump the count of what it read, a writer could drop the last reference
+// in between, so a load first publishes the pointer in the hazard slot of its
+// thread and a writer only gives up its reference once no slot holds it anymore.
+// loads that start after the swap can't see the old pointer, so a writer only
+// waits for the loads that were already running
+pub struct AtomicArc<T> {
+    pointer: AtomicPtr<T>,
+}
+
+unsafe impl<T: Send + Sync> Send for AtomicArc<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomicArc<T> {}
+
+// on its own cache line, so loads on different threads don't contend
+#[repr(align(64))]
+struct Slot {
+    pointer: AtomicPtr<()>,
+}
+
+// every slot ever handed out, slots are never freed
+static SLOTS: Mutex<Vec<&'static Slot>> = Mutex::new(Vec::new());
+// slots of threads that exited
+static FREE_SLOTS: Mutex<Vec<&'static Slot>> = Mutex::new(Vec::new());
+
+struct LocalSlot(&'static Slot);
+
+impl Drop for LocalSlot {
+    fn drop(&mut self) {
+        FREE_SLOTS.lock().unwrap().push(self.0);
+    }
+}
+
+thread_local! {
+    static SLOT: LocalSlot = LocalSlot(take_slot());
+}
+
+fn take_slot() -> &'static Slot {
+    if let Some(slot) = FREE_SLOTS.lock().unwrap().pop() {
+        return slot;
+    }
+    let slot = Box::leak(Box::new(Slot {
+        pointer: AtomicPtr::new(ptr::null_mut()),
+    }));
+    SLOTS.lock().unwrap().push(slot);
+    slot
+}
+
+fn with_slot<R>(f: impl FnOnce(&Slot) -> R) -> R {
+    match SLOT.try_with(|slot| slot.0) {
+        Ok(slot) => f(slot),
+        Err(_) => {
+            // the thread is exiting, borrow a slot just for this call
+            let slot = LocalSlot(take_slot());
+            f(slot.0)
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T> AtomicArc<T> {
+    pub fn new(value: Arc<T>) -> Self {
+        Self {
+            pointer: AtomicPtr::new(Arc::into_raw(value) as *mut T),
+        }
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        with_slot(|slot| {
+            let mut ptr = self.pointer.load(Ordering::Relaxed);
+            loop {
+                slot.pointer.store(ptr as *mut (), Ordering::Relaxed);
+                // pairs with the fence in wait_for_loads: either the writer sees
+                // our slot or we see the pointer it swapped in
+                fence(Ordering::SeqCst);
+                let current = self.pointer.load(Ordering::Acquire);
+                if current == ptr {
+                    // the address may have been freed and reused in between,
+                    // only the pointer we just loaded belongs to the current value
+                    ptr = current;
+                    break;
+                }
+                ptr = current;
+            }
+            // the AtomicArc still holds its reference, so the value is alive here
+            let arc = ManuallyDrop::new(unsafe { Arc::from_raw(ptr) });
+            let loaded = Arc::clone(&arc);
+            // Release, so the writer sees the increment before it drops its reference
+            slot.pointer.store(ptr::null_mut(), Ordering::Release);
+            loaded
+        })
+    }
+
+    pub fn store(&self, value: Arc<T>) {
+        drop(self.swap(value));
+    }
+
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        let new = Arc::into_raw(value) as *mut T;
+        let old = self.pointer.swap(new, Ordering::AcqRel);
+        wait_for_loads(old);
+        unsafe { Arc::from_raw(old) }
+    }
+
+    // replaces the value if it still is `current`, returns the previous Arc on success
+    // and gives `new` back on failure
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Result<Arc<T>, Arc<T>> {
+        let expected = Arc::as_ptr(current) as *mut T;
+        let new = Arc::into_raw(new) as *mut T;
+        match self
+            .pointer
+            .compare_exchange(expected, new, Ordering::AcqRel, Ordering::Relaxed)
+        {
+            Ok(old) => {
+                wait_for_loads(old);
+                Ok(unsafe { Arc::from_raw(old) })
+            }
+            Err(_) => Err(unsafe { Arc::from_raw(new) }),
+        }
+    }
+
+    pub fn into_inner(self) -> Arc<T> {
+        let this = ManuallyDrop::new(self);
+        unsafe { Arc::from_raw(this.pointer.load(Ordering::Relaxed)) }
+    }
+}
+
+// a load that started before our swap might still be about to clone the old
+// value, it has it in its slot then
+fn wait_for_loads<T>(old: *mut T) {
+    fence(Ordering::SeqCst);
+    let slots = SLOTS.lock().unwrap().clone();
+    for slot in slots {
+        while slot.pointer.load(Ordering::Acquire) == old as *mut () {
+            hint::spin_loop();
+        }
+    }
+}
+
+impl<T> Drop for AtomicArc<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Arc::from_raw(*self.pointer.get_mut()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        hint::black_box,
+        sync::atomic::{AtomicBool, AtomicUsize},
+        thread,
+    };
+
+    #[test]
+    fn test_atomic_arc() {
+        let a = Arc::new(1);
+        let b = Arc::new(2);
+        let x = AtomicArc::new(a.clone());
+        assert_eq!(*x.load(), 1);
+        assert_eq!(Arc::strong_count(&a), 2);
+
+        let old = x.swap(b.clone());
+        assert!(Arc::ptr_eq(&old, &a));
+        drop(old);
+        assert_eq!(Arc::strong_count(&a), 1);
+
+        // a is no longer the current value
+        let Err(c) = x.compare_and_swap(&a, Arc::new(3)) else {
+            panic!("a was replaced by b");
+        };
+        assert_eq!(*c, 3);
+
+        let old = x.compare_and_swap(&b, c).ok().unwrap();
+        assert!(Arc::ptr_eq(&old, &b));
+        assert_eq!(*x.load(), 3);
+
+        x.store(Arc::new(4));
+        assert_eq!(*x.into_inner(), 4);
+        assert_eq!(Arc::strong_count(&b), 2);
+    }
+
+    #[test]
+    fn test_atomic_arc_concurrent() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Config(usize);
+
+        impl Drop for Config {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let iterations = if cfg!(miri) { 20 } else { 1000 };
+        let x = AtomicArc::new(Arc::new(Config(0)));
+
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..iterations {
+                        // versions only ever go up for a single reader
+                        let config = x.load();
+                        assert!(config.0 >= last);
+                        last = config.0;
+                    }
+                });
+            }
+            s.spawn(|| {
+                for i in 1..=iterations {
+                    x.store(Arc::new(Config(i)));
+                }
+            });
+        });
+
+        assert_eq!(x.load().0, iterations);
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), iterations + 1);
+    }
+
+    #[test]
+    fn test_atomic_arc_continuous_loads() {
+        let iterations = if cfg!(miri) { 20 } else { 1000 };
+        let x = AtomicArc::new(Arc::new(0));
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        black_box(x.load());
+                    }
+                });
+            }
+            // the readers never stop loading, the writer still gets through
+            for i in 1..=iterations {
+                x.store(Arc::new(i));
+            }
+            stop.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(*x.load(), iterations);
+    }
+}
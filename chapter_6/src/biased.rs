@@ -0,0 +1,724 @@
+use crate::Arc;
+use std::{
+    cell::{Cell, UnsafeCell},
+    hint::black_box,
+    mem::{self, ManuallyDrop},
+    ops::Deref,
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{fence, AtomicBool, AtomicIsize, AtomicUsize, Ordering},
+        Arc as StdArc, Mutex,
+    },
+    thread,
+    time::SystemTime,
+};
+
+// biased reference counting: the thread that created the value counts its
+// clones and drops in `biased` without atomics, every other thread uses `shared`.
+// only the sum of both is the real count, so `shared` can go negative when a
+// clone made by the owner is dropped somewhere else. the value can only be freed
+// once the owner has merged its count into `shared`, which it does when its own
+// count drops to zero, or when another thread pushes `shared` below zero and
+// queues the value with the owner
+//
+// `shared` holds the count in steps of 4 and the flags in the lowest two bits,
+// a value is dropped once `shared` is exactly MERGED
+//
+// Weaks upgrade through `shared`, so until the owner merged its count a value whose
+// last BiasedArc is gone can still be upgraded, it's only dropped by the merge
+//
+// the API follows Arc, with two differences: T has to be 'static, and a value queued
+// with the owner is only freed once the owner clones or drops a BiasedArc, calls
+// merge_queued or exits. an owner that stays idle holds on to that memory
+const MERGED: isize = 1;
+const QUEUED: isize = 2;
+const ONE: isize = 4;
+
+struct BiasedData<T> {
+    owner: StdArc<ThreadRecord>,
+    // only changed by the owner, or by whoever merges after the owner exited.
+    // plain loads and stores, it's atomic so strong_count can read it anywhere
+    biased: AtomicUsize,
+    // only touched by the owner, or by whoever merges after the owner exited
+    merged: Cell<bool>,
+    shared: AtomicIsize,
+    // number of Weaks + 1 for all BiasedArcs together
+    alloc_count: AtomicUsize,
+    data: UnsafeCell<ManuallyDrop<T>>,
+}
+
+// T has to be 'static, the value can outlive the last BiasedArc while it sits in a queue
+pub struct BiasedArc<T: 'static> {
+    pointer: NonNull<BiasedData<T>>,
+}
+
+pub struct Weak<T: 'static> {
+    pointer: NonNull<BiasedData<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for BiasedArc<T> {}
+unsafe impl<T: Send + Sync> Sync for BiasedArc<T> {}
+
+unsafe impl<T: Send + Sync> Send for Weak<T> {}
+unsafe impl<T: Send + Sync> Sync for Weak<T> {}
+
+struct Queued {
+    data: *const (),
+    merge: unsafe fn(*const ()),
+}
+
+unsafe impl Send for Queued {}
+
+struct ThreadRecord {
+    // None once the thread exited, values are then merged by whoever would queue them
+    queue: Mutex<Option<Vec<Queued>>>,
+    pending: AtomicBool,
+}
+
+struct ThreadHandle(StdArc<ThreadRecord>);
+
+impl Drop for ThreadHandle {
+    fn drop(&mut self) {
+        let queued = self.0.queue.lock().unwrap().take().unwrap();
+        for q in queued {
+            unsafe { (q.merge)(q.data) };
+        }
+    }
+}
+
+thread_local! {
+    static THREAD: ThreadHandle = ThreadHandle(StdArc::new(ThreadRecord {
+        queue: Mutex::new(Some(Vec::new())),
+        pending: AtomicBool::new(false),
+    }));
+}
+
+// merges the counts of everything other threads queued with the current thread,
+// happens on its own when the owner clones or drops a BiasedArc
+pub fn merge_queued() {
+    let _ = THREAD.try_with(|thread| {
+        // a plain load first, this runs on every clone and drop of the owner
+        if thread.0.pending.load(Ordering::Relaxed)
+            && thread.0.pending.swap(false, Ordering::Acquire)
+        {
+            let queued = thread.0.queue.lock().unwrap().as_mut().map(std::mem::take);
+            for q in queued.into_iter().flatten() {
+                unsafe { (q.merge)(q.data) };
+            }
+        }
+    });
+}
+
+// moves the owner's count into `shared` and takes the value out of the queue,
+// dropping it if nothing refers to it anymore
+unsafe fn merge_queued_data<T>(data: *const ()) {
+    let ptr = data as *mut BiasedData<T>;
+    let data = &*ptr;
+    let mut delta = data.biased.load(Ordering::Relaxed) as isize * ONE - QUEUED;
+    data.biased.store(0, Ordering::Relaxed);
+    if !data.merged.replace(true) {
+        delta += MERGED;
+    }
+    if data.shared.fetch_add(delta, Ordering::AcqRel) + delta == MERGED {
+        drop_value(ptr);
+    }
+}
+
+// the count reached zero, the memory is freed once the Weaks are gone too
+unsafe fn drop_value<T>(ptr: *mut BiasedData<T>) {
+    ManuallyDrop::drop(&mut *(*ptr).data.get());
+    release_alloc(ptr);
+}
+
+unsafe fn release_alloc<T>(ptr: *mut BiasedData<T>) {
+    if (*ptr).alloc_count.fetch_sub(1, Ordering::Release) == 1 {
+        fence(Ordering::Acquire);
+        drop(Box::from_raw(ptr));
+    }
+}
+
+#[allow(dead_code)]
+impl<T> BiasedArc<T> {
+    pub fn new(value: T) -> Self {
+        let data = Box::new(BiasedData {
+            owner: THREAD.with(|thread| thread.0.clone()),
+            biased: AtomicUsize::new(1),
+            merged: Cell::new(false),
+            shared: AtomicIsize::new(0),
+            alloc_count: AtomicUsize::new(1),
+            data: UnsafeCell::new(ManuallyDrop::new(value)),
+        });
+        BiasedArc {
+            pointer: NonNull::from(Box::leak(data)),
+        }
+    }
+
+    fn data(&self) -> &BiasedData<T> {
+        unsafe { self.pointer.as_ref() }
+    }
+
+    // true if the current thread may use the non atomic count
+    fn is_biased(&self) -> bool {
+        let data = self.data();
+        // other threads must not even read `merged`
+        THREAD
+            .try_with(|thread| StdArc::ptr_eq(&thread.0, &data.owner))
+            .unwrap_or(false)
+            && !data.merged.get()
+    }
+
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.pointer == b.pointer
+    }
+
+    pub fn into_raw(arc: Self) -> *const T {
+        let arc = ManuallyDrop::new(arc);
+        // through the raw pointer, so from_raw gets back the provenance of the whole allocation
+        unsafe { ptr::addr_of!((*arc.pointer.as_ptr()).data) as *const T }
+    }
+
+    // SAFETY: ptr must come from BiasedArc::into_raw and every from_raw must match an into_raw
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        let data = ptr.byte_sub(mem::offset_of!(BiasedData<T>, data)) as *mut BiasedData<T>;
+        BiasedArc {
+            pointer: NonNull::new_unchecked(data),
+        }
+    }
+
+    // exact on the owner and once the owner merged its count, other threads
+    // read the two counts one after the other, so it's only an estimate there
+    pub fn strong_count(arc: &Self) -> usize {
+        let data = arc.data();
+        // biased first, a merge in between then counts the owner twice instead of not at all
+        let biased = data.biased.load(Ordering::Relaxed) as isize;
+        let shared = data.shared.load(Ordering::Acquire);
+        let count = if shared & MERGED != 0 {
+            shared >> 2
+        } else {
+            biased + (shared >> 2)
+        };
+        count.max(0) as usize
+    }
+
+    pub fn weak_count(arc: &Self) -> usize {
+        let n = arc.data().alloc_count.load(Ordering::Acquire);
+        // get_mut has the weak count locked, meaning there were no weaks
+        if n == usize::MAX {
+            return 0;
+        }
+        // all the BiasedArcs together hold one alloc_count
+        n - 1
+    }
+
+    // only the owner knows its own count for sure, so other threads
+    // can't tell that they have the only BiasedArc until it's merged
+    fn is_unique(&self) -> bool {
+        let data = self.data();
+        let shared = data.shared.load(Ordering::Acquire);
+        if self.is_biased() {
+            data.biased.load(Ordering::Relaxed) as isize + (shared >> 2) == 1
+        } else {
+            shared == ONE | MERGED
+        }
+    }
+
+    pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
+        // lock out new Weaks the same way Arc::get_mut does
+        if arc
+            .data()
+            .alloc_count
+            .compare_exchange(1, usize::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+        let is_unique = arc.is_unique();
+        arc.data().alloc_count.store(1, Ordering::Release);
+
+        if !is_unique {
+            return None;
+        }
+        unsafe { Some(&mut *arc.data().data.get()) }
+    }
+
+    // returns the value if this is the only BiasedArc, with the same
+    // limits as get_mut. outstanding Weaks can't be upgraded afterwards
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        if arc.is_biased() {
+            // nothing queued may touch the value after we took it
+            merge_queued();
+        }
+
+        let data = arc.data();
+        let is_unique = if arc.is_biased() {
+            // the shared count must be empty without anything queued,
+            // the same exchange that a Weak upgrading would have to beat
+            data.biased.load(Ordering::Relaxed) == 1
+                && data
+                    .shared
+                    .compare_exchange(0, MERGED, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+        } else {
+            data.shared
+                .compare_exchange(ONE | MERGED, MERGED, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        };
+        if !is_unique {
+            return Err(arc);
+        }
+        data.biased.store(0, Ordering::Relaxed);
+        data.merged.set(true);
+
+        let arc = ManuallyDrop::new(arc);
+        unsafe {
+            let value = ManuallyDrop::take(&mut *arc.data().data.get());
+            release_alloc(arc.pointer.as_ptr());
+            Ok(value)
+        }
+    }
+
+    pub fn downgrade(arc: &Self) -> Weak<T> {
+        let mut n = arc.data().alloc_count.load(Ordering::Relaxed);
+        loop {
+            if n == usize::MAX {
+                std::hint::spin_loop();
+                n = arc.data().alloc_count.load(Ordering::Relaxed);
+                continue;
+            }
+            assert!(n < usize::MAX - 1);
+            if let Err(e) = arc.data().alloc_count.compare_exchange_weak(
+                n,
+                n + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                n = e;
+                continue;
+            }
+            return Weak {
+                pointer: arc.pointer,
+            };
+        }
+    }
+
+    fn queue(&self) {
+        let data = self.data();
+        let mut queue = data.owner.queue.lock().unwrap();
+        match queue.as_mut() {
+            Some(queue) => {
+                queue.push(Queued {
+                    data: self.pointer.as_ptr() as *const (),
+                    merge: merge_queued_data::<T>,
+                });
+                data.owner.pending.store(true, Ordering::Release);
+            }
+            None => {
+                // the owner is gone, its count won't change anymore
+                drop(queue);
+                unsafe { merge_queued_data::<T>(self.pointer.as_ptr() as *const ()) };
+            }
+        }
+    }
+}
+
+impl<T> Deref for BiasedArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.data().data.get() }
+    }
+}
+
+impl<T> Clone for BiasedArc<T> {
+    fn clone(&self) -> Self {
+        if self.is_biased() {
+            // so an owner that only clones still frees what others queued,
+            // this may merge our own value as well
+            merge_queued();
+        }
+
+        let data = self.data();
+        if self.is_biased() {
+            let n = data.biased.load(Ordering::Relaxed);
+            data.biased.store(n + 1, Ordering::Relaxed);
+        } else if data.shared.fetch_add(ONE, Ordering::Relaxed) > isize::MAX / 2 {
+            std::process::abort();
+        }
+        BiasedArc {
+            pointer: self.pointer,
+        }
+    }
+}
+
+impl<T> Drop for BiasedArc<T> {
+    fn drop(&mut self) {
+        if self.is_biased() {
+            // this may merge our own value as well
+            merge_queued();
+        }
+
+        let data = self.data();
+        if self.is_biased() {
+            let n = data.biased.load(Ordering::Relaxed) - 1;
+            data.biased.store(n, Ordering::Relaxed);
+            if n == 0 {
+                data.merged.set(true);
+                // if it is queued the value gets dropped when the queue is merged
+                if data.shared.fetch_add(MERGED, Ordering::AcqRel) == 0 {
+                    unsafe { drop_value(self.pointer.as_ptr()) };
+                }
+            }
+            return;
+        }
+
+        let mut old = data.shared.load(Ordering::Relaxed);
+        loop {
+            let mut new = old - ONE;
+            // the first time the count goes below zero the owner has to merge
+            let queue = old & (MERGED | QUEUED) == 0 && new < 0;
+            if queue {
+                new |= QUEUED;
+            }
+            match data
+                .shared
+                .compare_exchange_weak(old, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) if queue => return self.queue(),
+                Ok(_) if new == MERGED => {
+                    fence(Ordering::Acquire);
+                    unsafe { drop_value(self.pointer.as_ptr()) };
+                    return;
+                }
+                Ok(_) => return,
+                Err(e) => old = e,
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T> Weak<T> {
+    fn data(&self) -> &BiasedData<T> {
+        unsafe { self.pointer.as_ref() }
+    }
+
+    // always counts in `shared`, the owner's count is only for clones it made itself
+    pub fn upgrade(&self) -> Option<BiasedArc<T>> {
+        let data = self.data();
+        let mut n = data.shared.load(Ordering::Relaxed);
+        loop {
+            if n == MERGED {
+                return None;
+            }
+            if n > isize::MAX / 2 {
+                std::process::abort();
+            }
+            match data.shared.compare_exchange_weak(
+                n,
+                n + ONE,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(BiasedArc {
+                        pointer: self.pointer,
+                    })
+                }
+                Err(e) => n = e,
+            }
+        }
+    }
+}
+
+impl<T> Clone for Weak<T> {
+    fn clone(&self) -> Self {
+        if self.data().alloc_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        Weak {
+            pointer: self.pointer,
+        }
+    }
+}
+
+impl<T> Drop for Weak<T> {
+    fn drop(&mut self) {
+        unsafe { release_alloc(self.pointer.as_ptr()) };
+    }
+}
+
+// clones and drops on the owning thread while other threads do the same,
+// once with our Arc and once with BiasedArc
+pub fn bench() {
+    const N: usize = 10_000_000;
+    let threads = thread::available_parallelism()
+        .map_or(4, |n| n.get())
+        .clamp(2, 8)
+        - 1;
+
+    fn run<A: Clone + Send + Sync>(name: &str, arc: A, threads: usize) {
+        let stop = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        black_box(arc.clone());
+                    }
+                });
+            }
+
+            let start_time = SystemTime::now();
+            for _ in 0..N {
+                black_box(arc.clone());
+            }
+            println!("{name} owner clone and drop: {:?}", start_time.elapsed());
+            stop.store(true, Ordering::Relaxed);
+        });
+    }
+
+    for threads in [0, threads] {
+        println!("with {threads} other threads");
+        run("Arc", Arc::new(0), threads);
+        run("BiasedArc", BiasedArc::new(0), threads);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    struct DetectDrop<'a>(&'a AtomicUsize);
+
+    impl Drop for DetectDrop<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_biased_arc() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let y = x.clone();
+        assert!(BiasedArc::ptr_eq(&x, &y));
+        // the owner never touched the shared count
+        assert_eq!(x.data().shared.load(Ordering::Relaxed), 0);
+        assert_eq!(x.data().biased.load(Ordering::Relaxed), 2);
+
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(y);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_biased_arc_queued() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let y = x.clone();
+        // the other thread takes the shared count below zero and queues x with us
+        thread::spawn(move || drop(y)).join().unwrap();
+        assert_eq!(x.data().shared.load(Ordering::Relaxed), -ONE | QUEUED);
+
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+        // a clone on the owner merges the queue as well
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let y = x.clone();
+        thread::spawn(move || drop(y)).join().unwrap();
+        let y = x.clone();
+        assert_eq!(x.data().shared.load(Ordering::Relaxed), MERGED + 2 * ONE);
+        drop((x, y));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+
+        // the owner's count runs out before it gets to merge the queue
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let (y, u) = (x.clone(), x.clone());
+        let (v, w) = thread::spawn(move || {
+            drop(y);
+            (u.clone(), u.clone())
+        })
+        .join()
+        .unwrap();
+        x.data().owner.pending.store(false, Ordering::Relaxed);
+        let data = x.pointer;
+        drop((x, v, w));
+        assert_eq!(
+            unsafe { data.as_ref() }.shared.load(Ordering::Relaxed),
+            MERGED | QUEUED
+        );
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+
+        THREAD.with(|thread| thread.0.pending.store(true, Ordering::Relaxed));
+        merge_queued();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_biased_arc_raw() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let ptr = BiasedArc::into_raw(x.clone());
+        assert_eq!(BiasedArc::strong_count(&x), 2);
+        assert!(ptr::eq(ptr, &*x));
+
+        // dropped on another thread once it's brought back
+        let y = unsafe { BiasedArc::from_raw(ptr) };
+        thread::spawn(move || drop(y)).join().unwrap();
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_biased_arc_owner_exited() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let x = thread::spawn(|| {
+            let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+            x.clone()
+        })
+        .join()
+        .unwrap();
+
+        // nobody is left to merge, so this thread does it
+        let y = x.clone();
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(y);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_biased_arc_threads() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let iterations = if cfg!(miri) { 10 } else { 10_000 };
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let x = x.clone();
+                s.spawn(move || {
+                    let mut clones = Vec::new();
+                    for i in 0..iterations {
+                        clones.push(x.clone());
+                        if i % 3 == 0 {
+                            clones.clear();
+                        }
+                    }
+                });
+            }
+            for _ in 0..iterations {
+                black_box(x.clone());
+            }
+        });
+
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(x);
+        merge_queued();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_biased_arc_weak() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        assert_eq!(BiasedArc::weak_count(&x), 0);
+        let w = BiasedArc::downgrade(&x);
+        assert_eq!(BiasedArc::strong_count(&x), 1);
+        assert_eq!(BiasedArc::weak_count(&x), 1);
+
+        let y = w.upgrade().unwrap();
+        assert_eq!(BiasedArc::strong_count(&x), 2);
+        // upgrading on another thread, and dropping it there
+        let v = w.clone();
+        thread::spawn(move || {
+            let z = v.upgrade().unwrap();
+            assert_eq!(BiasedArc::strong_count(&z), 3);
+        })
+        .join()
+        .unwrap();
+
+        drop(x);
+        assert_eq!(BiasedArc::strong_count(&y), 1);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(y);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        assert!(w.upgrade().is_none());
+        assert_eq!(w.data().alloc_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_biased_arc_get_mut() {
+        let mut x = BiasedArc::new(1);
+        *BiasedArc::get_mut(&mut x).unwrap() += 1;
+
+        let y = x.clone();
+        assert!(BiasedArc::get_mut(&mut x).is_none());
+        // the clone is dropped on another thread and queued with us
+        thread::spawn(move || drop(y)).join().unwrap();
+        *BiasedArc::get_mut(&mut x).unwrap() += 1;
+
+        let w = BiasedArc::downgrade(&x);
+        assert!(BiasedArc::get_mut(&mut x).is_none());
+        drop(w);
+
+        // other threads only know once the owner's count is merged
+        let x = thread::spawn(move || {
+            assert!(BiasedArc::get_mut(&mut x).is_none());
+            x
+        })
+        .join()
+        .unwrap();
+        assert_eq!(*x, 3);
+
+        let mut y = thread::scope(|s| s.spawn(|| x.clone()).join().unwrap());
+        drop(x);
+        thread::spawn(move || {
+            *BiasedArc::get_mut(&mut y).unwrap() += 1;
+            assert_eq!(*y, 4);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_biased_arc_try_unwrap() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let y = x.clone();
+        let x = BiasedArc::try_unwrap(x).err().unwrap();
+        drop(y);
+        let value = BiasedArc::try_unwrap(x).ok().unwrap();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(value);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+        // weaks don't keep the value, they just can't upgrade anymore
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let w = BiasedArc::downgrade(&x);
+        let value = BiasedArc::try_unwrap(x).ok().unwrap();
+        assert!(w.upgrade().is_none());
+        drop(value);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+
+        // after the merge it works on any thread
+        let x = BiasedArc::new(DetectDrop(&NUM_DROPS));
+        let y = thread::scope(|s| s.spawn(|| x.clone()).join().unwrap());
+        drop(x);
+        thread::spawn(move || {
+            let value = BiasedArc::try_unwrap(y).ok().unwrap();
+            drop(value);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+        drop(w);
+    }
+}
@@ -1,53 +1,300 @@
+mod atomic_arc;
+mod biased;
+mod no_weak;
+mod thin_arc;
+
+use allocator_api::{Allocator, Global};
 use std::ops::Deref;
 use std::{
+    alloc::{handle_alloc_error, Layout},
     cell::UnsafeCell,
-    mem::ManuallyDrop,
-    ptr::NonNull,
+    mem::{self, ManuallyDrop, MaybeUninit},
+    ptr::{self, NonNull},
     sync::atomic::{fence, AtomicUsize, Ordering},
-    usize,
 };
 
 fn main() {
-    println!("Hello, world!");
+    no_weak::bench();
+    biased::bench();
 }
 
-struct ArcData<T> {
-    data: UnsafeCell<ManuallyDrop<T>>,
+// repr(C) so the layout for unsized values can be computed by hand,
+// the possibly unsized data has to come last
+#[repr(C)]
+struct ArcData<T: ?Sized> {
     data_count: AtomicUsize,
     alloc_count: AtomicUsize,
+    data: UnsafeCell<ManuallyDrop<T>>,
 }
 
-struct Arc<T> {
+// every Arc and Weak keeps its own copy of the allocator,
+// which is free for Global and a reference for arenas
+struct Arc<T: ?Sized, A: Allocator = Global> {
     pointer: NonNull<ArcData<T>>,
+    alloc: A,
 }
 
-struct Weak<T> {
+struct Weak<T: ?Sized, A: Allocator = Global> {
     pointer: NonNull<ArcData<T>>,
+    alloc: A,
+}
+
+unsafe impl<T: ?Sized, A: Allocator + Send> Send for Arc<T, A> where T: Send + Sync {}
+unsafe impl<T: ?Sized, A: Allocator + Sync> Sync for Arc<T, A> where T: Send + Sync {}
+
+unsafe impl<T: ?Sized, A: Allocator + Send> Send for Weak<T, A> where T: Send + Sync {}
+unsafe impl<T: ?Sized, A: Allocator + Sync> Sync for Weak<T, A> where T: Send + Sync {}
+
+// layout of an ArcData holding a value with the given layout
+fn arc_data_layout(value: Layout) -> Layout {
+    Layout::new::<ArcData<()>>()
+        .extend(value)
+        .unwrap()
+        .0
+        .pad_to_align()
+}
+
+// offset of the data in an ArcData holding a value with the given alignment
+fn data_offset(align: usize) -> usize {
+    Layout::new::<ArcData<()>>()
+        .extend(Layout::from_size_align(0, align).unwrap())
+        .unwrap()
+        .1
 }
 
-unsafe impl<T> Send for Arc<T> where T: Send + Sync {}
-unsafe impl<T> Sync for Arc<T> where T: Sync + Sync {}
+// replaces the address of a possibly fat pointer and keeps its metadata,
+// which works because the address is the first half of a fat pointer
+unsafe fn set_data_ptr<T: ?Sized>(mut ptr: *mut T, data: *mut u8) -> *mut T {
+    ptr::write(&mut ptr as *mut *mut T as *mut *mut u8, data);
+    ptr
+}
 
-unsafe impl<T> Send for Weak<T> where T: Send + Sync {}
-unsafe impl<T> Sync for Weak<T> where T: Sync + Sync {}
+// gives up one alloc_count, the last one hands the memory back to the allocator
+unsafe fn release_alloc<T: ?Sized, A: Allocator>(pointer: NonNull<ArcData<T>>, alloc: &A) {
+    if pointer.as_ref().alloc_count.fetch_sub(1, Ordering::Release) == 1 {
+        fence(Ordering::Acquire);
+        let layout = Layout::for_value(pointer.as_ref());
+        alloc.deallocate(pointer.as_ptr() as *mut u8, layout);
+    }
+}
 
+#[allow(dead_code)]
 impl<T> Arc<T> {
     pub fn new(value: T) -> Self {
-        let ptr = unsafe {
-            NonNull::new_unchecked(Box::leak(Box::new(ArcData {
-                data: UnsafeCell::new(ManuallyDrop::new(value)),
-                data_count: AtomicUsize::new(1),
-                alloc_count: AtomicUsize::new(1),
-            })))
+        Arc::new_in(value, Global)
+    }
+
+    // hands `f` a Weak to the Arc that is being built, upgrading it fails
+    // until the value returned by `f` has been stored
+    pub fn new_cyclic(f: impl FnOnce(&Weak<T>) -> T) -> Self {
+        // no Arcs yet, the alloc_count belongs to the Weak for now
+        let uninit = Box::leak(Box::new(ArcData {
+            data: UnsafeCell::new(ManuallyDrop::new(MaybeUninit::<T>::uninit())),
+            data_count: AtomicUsize::new(0),
+            alloc_count: AtomicUsize::new(1),
+        }));
+        // MaybeUninit<T> has the same layout as T
+        let weak = Weak {
+            pointer: NonNull::from(uninit).cast::<ArcData<T>>(),
+            alloc: Global,
+        };
+
+        let value = f(&weak);
+        unsafe {
+            weak.data()
+                .unwrap()
+                .data
+                .get()
+                .write(ManuallyDrop::new(value))
+        };
+        // pairs with the Acquire in upgrade, so Weaks that were sent to other
+        // threads from inside `f` see the value once they can upgrade
+        weak.data().unwrap().data_count.store(1, Ordering::Release);
+
+        // the Weak's alloc_count is the one shared by all Arcs from now on
+        let weak = ManuallyDrop::new(weak);
+        Arc {
+            pointer: weak.pointer,
+            alloc: Global,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T, A: Allocator> Arc<T, A> {
+    pub fn new_in(value: T, alloc: A) -> Self {
+        unsafe {
+            let ptr =
+                Arc::<T, A>::allocate(Layout::new::<T>(), &alloc, |mem| mem as *mut ArcData<T>);
+            ptr::addr_of_mut!((*ptr).data).write(UnsafeCell::new(ManuallyDrop::new(value)));
+
+            Arc {
+                pointer: NonNull::new_unchecked(ptr),
+                alloc,
+            }
+        }
+    }
+
+    // clones the value into a new allocation unless this is the only Arc,
+    // Weaks to a value that was otherwise unique are left behind instead
+    pub fn make_mut(arc: &mut Self) -> &mut T
+    where
+        T: Clone,
+        A: Clone,
+    {
+        // lock out new Weaks the same way get_mut does
+        if arc
+            .data()
+            .alloc_count
+            .compare_exchange(1, usize::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            let is_unique = arc.data().data_count.load(Ordering::Relaxed) == 1;
+            arc.data().alloc_count.store(1, Ordering::Release);
+
+            if !is_unique {
+                *arc = Arc::new_in((**arc).clone(), arc.alloc.clone());
+            }
+        } else if arc
+            .data()
+            .data_count
+            .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            // the only Arc but there are Weaks, which can't upgrade anymore,
+            // so move the value out to an allocation of its own
+            fence(Ordering::Acquire);
+            let value = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+            let alloc = arc.alloc.clone();
+            let old = mem::replace(arc, Arc::new_in(value, alloc));
+            drop(Arc::into_weak(old));
+        } else {
+            *arc = Arc::new_in((**arc).clone(), arc.alloc.clone());
+        }
+
+        fence(Ordering::Acquire);
+        unsafe { &mut *arc.data().data.get() }
+    }
+
+    // returns the value if this is the only Arc, outstanding Weaks can't be upgraded afterwards
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        if arc
+            .data()
+            .data_count
+            .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(arc);
+        }
+        fence(Ordering::Acquire);
+
+        let value = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        drop(Arc::into_weak(arc));
+        Ok(value)
+    }
+
+    // drops the Arc and returns the value if it was the last one,
+    // unlike try_unwrap two racing calls can't both end up with None
+    pub fn into_inner(arc: Self) -> Option<T> {
+        let arc = ManuallyDrop::new(arc);
+        let alloc = unsafe { ptr::read(&arc.alloc) };
+        if arc.data().data_count.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+        fence(Ordering::Acquire);
+
+        let value = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        unsafe { release_alloc(arc.pointer, &alloc) };
+        Some(value)
+    }
+
+    // CoerceUnsized is unstable, so the unsizing coercion is done by `coerce`
+    // on a reference instead, e.g. Arc::unsize(arc, |v| v as &dyn Display)
+    pub fn unsize<U: ?Sized>(arc: Self, coerce: impl FnOnce(&T) -> &U) -> Arc<U, A> {
+        let value: *const U = coerce(&arc);
+        // it has to be the same value, only behind a fat pointer
+        assert!(
+            ptr::addr_eq(value, Arc::as_ptr(&arc))
+                && unsafe { mem::size_of_val(&*value) } == mem::size_of::<T>()
+                && unsafe { mem::align_of_val(&*value) } == mem::align_of::<T>(),
+            "coerce has to return the value it was given"
+        );
+
+        // the new Arc takes over both counts of the old one
+        let arc = ManuallyDrop::new(arc);
+        let pointer = unsafe {
+            set_data_ptr(
+                value as *mut U as *mut ArcData<U>,
+                arc.pointer.as_ptr() as *mut u8,
+            )
         };
+        Arc {
+            pointer: unsafe { NonNull::new_unchecked(pointer) },
+            alloc: unsafe { ptr::read(&arc.alloc) },
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: ?Sized> Arc<T> {
+    // leaks the Arc, the pointer has to be turned back with from_raw to release it
+    pub fn into_raw(arc: Self) -> *const T {
+        let ptr = Arc::as_ptr(&arc);
+        mem::forget(arc);
+        ptr
+    }
+
+    // SAFETY: ptr must come from Arc::into_raw and every from_raw must match an into_raw
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // the value is still alive, so its alignment tells us where the ArcData starts
+        let offset = data_offset(mem::align_of_val(&*ptr));
+        let data = ptr.byte_sub(offset) as *mut ArcData<T>;
+        Arc {
+            pointer: NonNull::new_unchecked(data),
+            alloc: Global,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: ?Sized, A: Allocator> Arc<T, A> {
+    // allocates an ArcData for a value with the given layout and sets both counts to 1,
+    // `to_arc_data` turns the allocation into a pointer carrying T's metadata
+    unsafe fn allocate(
+        value: Layout,
+        alloc: &A,
+        to_arc_data: impl FnOnce(*mut u8) -> *mut ArcData<T>,
+    ) -> *mut ArcData<T> {
+        let layout = arc_data_layout(value);
+        let mem = alloc.allocate(layout);
+        if mem.is_null() {
+            handle_alloc_error(layout);
+        }
 
-        Arc { pointer: ptr }
+        let ptr = to_arc_data(mem);
+        ptr::addr_of_mut!((*ptr).data_count).write(AtomicUsize::new(1));
+        ptr::addr_of_mut!((*ptr).alloc_count).write(AtomicUsize::new(1));
+        ptr
     }
 
     fn data(&self) -> &ArcData<T> {
         unsafe { self.pointer.as_ref() }
     }
 
+    // for an Arc whose data_count was already released, the alloc_count
+    // it shared with the other Arcs now belongs to the returned Weak
+    fn into_weak(arc: Self) -> Weak<T, A> {
+        let arc = ManuallyDrop::new(arc);
+        Weak {
+            pointer: arc.pointer,
+            alloc: unsafe { ptr::read(&arc.alloc) },
+        }
+    }
+
+    pub fn allocator(arc: &Self) -> &A {
+        &arc.alloc
+    }
+
     pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
         if arc
             .data()
@@ -68,7 +315,35 @@ impl<T> Arc<T> {
         unsafe { Some(&mut *arc.data().data.get()) }
     }
 
-    pub fn downgrade(arc: &Self) -> Weak<T> {
+    pub fn strong_count(arc: &Self) -> usize {
+        arc.data().data_count.load(Ordering::Acquire)
+    }
+
+    pub fn weak_count(arc: &Self) -> usize {
+        let n = arc.data().alloc_count.load(Ordering::Acquire);
+        // get_mut has the weak count locked, meaning there were no weaks
+        if n == usize::MAX {
+            return 0;
+        }
+        // all the Arcs together hold one alloc_count
+        n - 1
+    }
+
+    // only compares addresses, like std
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        ptr::addr_eq(a.pointer.as_ptr(), b.pointer.as_ptr())
+    }
+
+    pub fn as_ptr(arc: &Self) -> *const T {
+        // go through the raw pointer so from_raw can get back to the whole ArcData,
+        // ManuallyDrop<T> and UnsafeCell have the same layout as T
+        unsafe { ptr::addr_of_mut!((*arc.pointer.as_ptr()).data) as *const T }
+    }
+
+    pub fn downgrade(arc: &Self) -> Weak<T, A>
+    where
+        A: Clone,
+    {
         let mut n = arc.data().alloc_count.load(Ordering::Relaxed);
         loop {
             if n == usize::MAX {
@@ -88,18 +363,65 @@ impl<T> Arc<T> {
             }
             return Weak {
                 pointer: arc.pointer,
+                alloc: arc.alloc.clone(),
             };
         }
     }
 }
 
+#[allow(dead_code)]
 impl<T> Weak<T> {
-    fn data(&self) -> &ArcData<T> {
-        unsafe { self.pointer.as_ref() }
+    // a Weak that never had an allocation and can never be upgraded
+    pub fn new() -> Self {
+        Weak {
+            pointer: unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(usize::MAX)) },
+            alloc: Global,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: ?Sized, A: Allocator> Weak<T, A> {
+    fn is_dangling(&self) -> bool {
+        self.pointer.as_ptr().addr() == usize::MAX
+    }
+
+    fn data(&self) -> Option<&ArcData<T>> {
+        if self.is_dangling() {
+            return None;
+        }
+        Some(unsafe { self.pointer.as_ref() })
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.data()
+            .map_or(0, |data| data.data_count.load(Ordering::Acquire))
+    }
+
+    // like std, this is 0 once there are no Arcs left
+    pub fn weak_count(&self) -> usize {
+        let Some(data) = self.data() else {
+            return 0;
+        };
+        let weak = data.alloc_count.load(Ordering::Acquire);
+        let strong = data.data_count.load(Ordering::Acquire);
+        if strong == 0 {
+            return 0;
+        }
+        // the Arcs hold one alloc_count between them
+        weak - 1
     }
 
-    pub fn upgrade(&self) -> Option<Arc<T>> {
-        let mut n = self.data().data_count.load(Ordering::Relaxed);
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        ptr::addr_eq(self.pointer.as_ptr(), other.pointer.as_ptr())
+    }
+
+    pub fn upgrade(&self) -> Option<Arc<T, A>>
+    where
+        A: Clone,
+    {
+        let data = self.data()?;
+        let mut n = data.data_count.load(Ordering::Relaxed);
         loop {
             if n == 0 {
                 return None;
@@ -107,10 +429,10 @@ impl<T> Weak<T> {
 
             assert!(n < usize::MAX);
 
-            if let Err(e) = self.data().data_count.compare_exchange_weak(
+            if let Err(e) = data.data_count.compare_exchange_weak(
                 n,
                 n + 1,
-                Ordering::Relaxed,
+                Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
                 n = e;
@@ -119,12 +441,13 @@ impl<T> Weak<T> {
 
             return Some(Arc {
                 pointer: self.pointer,
+                alloc: self.alloc.clone(),
             });
         }
     }
 }
 
-impl<T> Deref for Arc<T> {
+impl<T: ?Sized, A: Allocator> Deref for Arc<T, A> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -133,7 +456,7 @@ impl<T> Deref for Arc<T> {
     }
 }
 
-impl<T> Clone for Arc<T> {
+impl<T: ?Sized, A: Allocator + Clone> Clone for Arc<T, A> {
     fn clone(&self) -> Self {
         if self.data().data_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
             std::process::abort();
@@ -141,49 +464,134 @@ impl<T> Clone for Arc<T> {
 
         Arc {
             pointer: self.pointer,
+            alloc: self.alloc.clone(),
         }
     }
 }
 
-impl<T> Clone for Weak<T> {
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Weak::new()
+    }
+}
+
+impl<T: ?Sized, A: Allocator + Clone> Clone for Weak<T, A> {
     fn clone(&self) -> Self {
-        if self.data().alloc_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
-            std::process::abort();
+        if let Some(data) = self.data() {
+            if data.alloc_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+                std::process::abort();
+            }
         }
 
         Weak {
             pointer: self.pointer,
+            alloc: self.alloc.clone(),
         }
     }
 }
 
-impl<T> Drop for Arc<T> {
+impl<T> From<Vec<T>> for Arc<[T]> {
+    fn from(mut v: Vec<T>) -> Self {
+        let len = v.len();
+        unsafe {
+            let ptr = Arc::allocate(Layout::array::<T>(len).unwrap(), &Global, |mem| {
+                ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut ArcData<[T]>
+            });
+            let data = ptr::addr_of_mut!((*ptr).data) as *mut T;
+            ptr::copy_nonoverlapping(v.as_ptr(), data, len);
+            // the elements now belong to the Arc, only the buffer is left to free
+            v.set_len(0);
+
+            Arc {
+                pointer: NonNull::new_unchecked(ptr),
+                alloc: Global,
+            }
+        }
+    }
+}
+
+impl<T: Clone> From<&[T]> for Arc<[T]> {
+    fn from(v: &[T]) -> Self {
+        Arc::from(v.to_vec())
+    }
+}
+
+impl<T> FromIterator<T> for Arc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Arc::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+
+impl From<String> for Arc<str> {
+    fn from(s: String) -> Self {
+        let bytes = ManuallyDrop::new(Arc::<[u8]>::from(s.into_bytes()));
+        // str has the same layout as [u8] and the bytes are valid utf-8
+        Arc {
+            pointer: unsafe { NonNull::new_unchecked(bytes.pointer.as_ptr() as *mut ArcData<str>) },
+            alloc: Global,
+        }
+    }
+}
+
+impl From<&str> for Arc<str> {
+    fn from(s: &str) -> Self {
+        Arc::from(String::from(s))
+    }
+}
+
+// the stable way to get an Arc<dyn Trait>: Arc::from(Box::new(value) as Box<dyn Trait>)
+impl<T: ?Sized> From<Box<T>> for Arc<T> {
+    fn from(b: Box<T>) -> Self {
+        let value = Layout::for_value(&*b);
+        let raw = Box::into_raw(b);
+        unsafe {
+            let ptr = Arc::allocate(value, &Global, |mem| {
+                set_data_ptr(raw as *mut ArcData<T>, mem)
+            });
+            ptr::copy_nonoverlapping(
+                raw as *const u8,
+                ptr::addr_of_mut!((*ptr).data) as *mut u8,
+                value.size(),
+            );
+            // free the box without dropping the value that was moved out
+            drop(Box::from_raw(raw as *mut ManuallyDrop<T>));
+
+            Arc {
+                pointer: NonNull::new_unchecked(ptr),
+                alloc: Global,
+            }
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Drop for Arc<T, A> {
     fn drop(&mut self) {
         if self.data().data_count.fetch_sub(1, Ordering::Release) == 1 {
             fence(Ordering::Acquire);
 
             unsafe { ManuallyDrop::drop(&mut *self.data().data.get()) };
-        }
 
-        drop(Weak {
-            pointer: self.pointer,
-        });
+            // the last Arc releases the alloc_count shared by all Arcs
+            unsafe { release_alloc(self.pointer, &self.alloc) };
+        }
     }
 }
 
-impl<T> Drop for Weak<T> {
+impl<T: ?Sized, A: Allocator> Drop for Weak<T, A> {
     fn drop(&mut self) {
-        if self.data().alloc_count.fetch_sub(1, Ordering::Release) == 1 {
-            fence(Ordering::Acquire);
-            unsafe { drop(Box::from_raw(self.pointer.as_ptr())) };
+        if !self.is_dangling() {
+            unsafe { release_alloc(self.pointer, &self.alloc) };
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Arc;
+    use super::{Allocator, Arc, Weak};
     use std::{
+        alloc::Layout,
+        cell::{Cell, UnsafeCell},
+        ptr,
         sync::atomic::{AtomicUsize, Ordering},
         thread,
     };
@@ -263,4 +671,362 @@ mod tests {
         assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
         assert!(z.upgrade().is_none());
     }
+
+    #[test]
+    fn test_counts() {
+        let x = Arc::new(5);
+        assert_eq!((Arc::strong_count(&x), Arc::weak_count(&x)), (1, 0));
+        assert_eq!(x.data().data_count.load(Ordering::Relaxed), 1);
+        assert_eq!(x.data().alloc_count.load(Ordering::Relaxed), 1);
+
+        let y = x.clone();
+        let w = Arc::downgrade(&x);
+        assert_eq!((Arc::strong_count(&x), Arc::weak_count(&x)), (2, 1));
+        assert_eq!((w.strong_count(), w.weak_count()), (2, 1));
+        // all Arcs share a single alloc_count
+        assert_eq!(x.data().alloc_count.load(Ordering::Relaxed), 2);
+
+        drop(x);
+        assert_eq!((Arc::strong_count(&y), Arc::weak_count(&y)), (1, 1));
+        assert_eq!(y.data().alloc_count.load(Ordering::Relaxed), 2);
+
+        drop(y);
+        assert_eq!((w.strong_count(), w.weak_count()), (0, 0));
+        assert_eq!(w.data().unwrap().alloc_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_ptr_eq() {
+        let x = Arc::new(5);
+        let y = x.clone();
+        let z = Arc::new(5);
+        assert!(Arc::ptr_eq(&x, &y));
+        assert!(!Arc::ptr_eq(&x, &z));
+        assert_eq!(Arc::as_ptr(&x), &*y as *const i32);
+
+        let w = Arc::downgrade(&x);
+        assert!(w.ptr_eq(&Arc::downgrade(&y)));
+        assert!(!w.ptr_eq(&Arc::downgrade(&z)));
+    }
+
+    #[test]
+    fn test_raw() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct NumDrops(&'static str);
+
+        impl Drop for NumDrops {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let x = Arc::new(NumDrops("hello"));
+        let ptr = Arc::into_raw(x.clone());
+        assert_eq!(unsafe { (*ptr).0 }, "hello");
+        assert_eq!(Arc::strong_count(&x), 2);
+
+        let y = unsafe { Arc::from_raw(ptr) };
+        assert!(Arc::ptr_eq(&x, &y));
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(y);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_dangling_weak() {
+        let w = Weak::<String>::new();
+        assert!(w.upgrade().is_none());
+        assert_eq!((w.strong_count(), w.weak_count()), (0, 0));
+
+        let v = w.clone();
+        assert!(v.ptr_eq(&w));
+        assert!(!v.ptr_eq(&Arc::downgrade(&Arc::new(String::new()))));
+    }
+
+    #[test]
+    fn test_new_cyclic() {
+        struct Node {
+            name: &'static str,
+            this: Weak<Node>,
+        }
+
+        let node = Arc::new_cyclic(|weak| {
+            // nothing to upgrade to yet
+            assert!(weak.upgrade().is_none());
+            assert_eq!(weak.strong_count(), 0);
+            Node {
+                name: "root",
+                this: weak.clone(),
+            }
+        });
+
+        let this = node.this.upgrade().unwrap();
+        assert!(Arc::ptr_eq(&node, &this));
+        assert_eq!(this.name, "root");
+        assert_eq!((Arc::strong_count(&node), Arc::weak_count(&node)), (2, 1));
+
+        drop(this);
+        let w = Arc::downgrade(&node);
+        drop(node);
+        assert!(w.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_new_cyclic_panic() {
+        let weak = Cell::new(None);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            Arc::<String>::new_cyclic(|w| {
+                weak.set(Some(w.clone()));
+                panic!("no value");
+            })
+        }));
+        assert!(result.is_err());
+        // the allocation is kept alive by the Weak that escaped, but never upgrades
+        let weak = weak.take().unwrap();
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.data().unwrap().alloc_count.load(Ordering::Relaxed), 1);
+    }
+
+    // a bump allocator over a fixed buffer that never reuses memory
+    #[repr(C, align(64))]
+    struct Arena {
+        buf: UnsafeCell<[u8; 1024]>,
+        used: AtomicUsize,
+        live: AtomicUsize,
+    }
+
+    unsafe impl Sync for Arena {}
+
+    unsafe impl Allocator for Arena {
+        fn allocate(&self, layout: Layout) -> *mut u8 {
+            let start = |used: usize| used.next_multiple_of(layout.align());
+            match self
+                .used
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                    let end = start(used) + layout.size();
+                    (end <= 1024).then_some(end)
+                }) {
+                Ok(used) => {
+                    self.live.fetch_add(1, Ordering::Relaxed);
+                    unsafe { (self.buf.get() as *mut u8).add(start(used)) }
+                }
+                Err(_) => ptr::null_mut(),
+            }
+        }
+
+        unsafe fn deallocate(&self, _ptr: *mut u8, _layout: Layout) {
+            self.live.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_new_in() {
+        let arena = Arena {
+            buf: UnsafeCell::new([0; 1024]),
+            used: AtomicUsize::new(0),
+            live: AtomicUsize::new(0),
+        };
+        let in_arena =
+            |p: *const String| (p as usize).wrapping_sub(arena.buf.get() as usize) < 1024;
+
+        let mut x = Arc::new_in(String::from("hello"), &arena);
+        assert!(in_arena(Arc::as_ptr(&x)));
+        assert!(ptr::eq(*Arc::allocator(&x), &arena));
+
+        let y = x.clone();
+        let w = Arc::downgrade(&x);
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(*w.upgrade().unwrap(), "hello"));
+        });
+
+        // the copy goes into the arena as well
+        Arc::make_mut(&mut x).push('!');
+        assert!(in_arena(Arc::as_ptr(&x)));
+        assert_eq!((x.as_str(), y.as_str()), ("hello!", "hello"));
+        assert_eq!(arena.live.load(Ordering::Relaxed), 2);
+
+        drop(y);
+        assert_eq!(arena.live.load(Ordering::Relaxed), 2);
+        drop(w);
+        assert_eq!(Arc::try_unwrap(x).ok().as_deref(), Some("hello!"));
+        assert_eq!(arena.live.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_try_unwrap() {
+        let x = Arc::new(String::from("hello"));
+        let y = x.clone();
+
+        let x = Arc::try_unwrap(x).unwrap_err();
+        drop(y);
+
+        let w = Arc::downgrade(&x);
+        assert_eq!(Arc::try_unwrap(x).ok(), Some(String::from("hello")));
+        // the value was moved out, the allocation lives on for the weak
+        assert!(w.upgrade().is_none());
+        assert_eq!(w.data().unwrap().alloc_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let x = Arc::new(String::from("hello"));
+        let y = x.clone();
+
+        let results = thread::scope(|s| {
+            let a = s.spawn(|| Arc::into_inner(x));
+            let b = s.spawn(|| Arc::into_inner(y));
+            [a.join().unwrap(), b.join().unwrap()]
+        });
+
+        // exactly one of them gets the value
+        assert_eq!(results.iter().flatten().count(), 1);
+        assert!(results.contains(&Some(String::from("hello"))));
+    }
+
+    #[test]
+    fn test_make_mut() {
+        static NUM_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, PartialEq)]
+        struct NumClones(i32);
+
+        impl Clone for NumClones {
+            fn clone(&self) -> Self {
+                NUM_CLONES.fetch_add(1, Ordering::Relaxed);
+                NumClones(self.0)
+            }
+        }
+
+        // unique, modified in place
+        let mut x = Arc::new(NumClones(1));
+        let ptr = Arc::as_ptr(&x);
+        Arc::make_mut(&mut x).0 = 2;
+        assert_eq!(Arc::as_ptr(&x), ptr);
+        assert_eq!(NUM_CLONES.load(Ordering::Relaxed), 0);
+
+        // shared, cloned and the other Arc keeps the old value
+        let y = x.clone();
+        Arc::make_mut(&mut x).0 = 3;
+        assert!(!Arc::ptr_eq(&x, &y));
+        assert_eq!((x.0, y.0), (3, 2));
+        assert_eq!(NUM_CLONES.load(Ordering::Relaxed), 1);
+        assert_eq!((Arc::strong_count(&x), Arc::strong_count(&y)), (1, 1));
+
+        // unique with a Weak, moved without cloning and the Weak is left behind
+        let w = Arc::downgrade(&x);
+        Arc::make_mut(&mut x).0 = 4;
+        assert_eq!(x.0, 4);
+        assert_eq!(NUM_CLONES.load(Ordering::Relaxed), 1);
+        assert!(w.upgrade().is_none());
+        assert_eq!((Arc::strong_count(&x), Arc::weak_count(&x)), (1, 0));
+        assert_eq!(w.data().unwrap().alloc_count.load(Ordering::Relaxed), 1);
+
+        // shared and with a Weak, cloned and the Weak still upgrades to the old value
+        let z = x.clone();
+        let w = Arc::downgrade(&z);
+        Arc::make_mut(&mut x).0 = 5;
+        assert_eq!(NUM_CLONES.load(Ordering::Relaxed), 2);
+        assert_eq!(w.upgrade().unwrap().0, 4);
+        assert_eq!(x.0, 5);
+    }
+
+    #[test]
+    fn test_slice() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct NumDrops(usize);
+
+        impl Drop for NumDrops {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let x: Arc<[NumDrops]> = (0..5).map(NumDrops).collect();
+        assert_eq!(x.len(), 5);
+        assert_eq!(x[3].0, 3);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+
+        let y = x.clone();
+        let w = Arc::downgrade(&x);
+        drop(x);
+        assert_eq!(w.upgrade().unwrap()[4].0, 4);
+
+        let ptr = Arc::into_raw(y);
+        let y = unsafe { Arc::from_raw(ptr) };
+        assert_eq!(Arc::strong_count(&y), 1);
+
+        drop(y);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 5);
+        assert!(w.upgrade().is_none());
+
+        let mut empty = Arc::<[i32]>::from(Vec::new());
+        assert!(empty.is_empty());
+        assert_eq!(Arc::get_mut(&mut empty), Some(&mut [][..]));
+
+        let mut z = Arc::<[i32]>::from(&[1, 2, 3][..]);
+        Arc::get_mut(&mut z).unwrap()[0] = 10;
+        assert_eq!(*z, [10, 2, 3]);
+    }
+
+    #[test]
+    fn test_str() {
+        let x = Arc::<str>::from(String::from("hello"));
+        let y = Arc::<str>::from("world");
+        assert_eq!(format!("{} {}", &*x, &*y), "hello world");
+
+        let ptr = Arc::into_raw(x);
+        let x = unsafe { Arc::from_raw(ptr) };
+        assert_eq!(&*x, "hello");
+    }
+
+    trait Shape {
+        fn area(&self) -> u64;
+    }
+
+    // over aligned, so the data doesn't directly follow the counts
+    #[repr(align(64))]
+    struct Square(u64, &'static AtomicUsize);
+
+    impl Shape for Square {
+        fn area(&self) -> u64 {
+            self.0 * self.0
+        }
+    }
+
+    impl Drop for Square {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_dyn() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let x: Arc<dyn Shape> = Arc::from(Box::new(Square(3, &NUM_DROPS)) as Box<dyn Shape>);
+        assert_eq!(x.area(), 9);
+        assert_eq!(Arc::as_ptr(&x) as *const () as usize % 64, 0);
+
+        let y = Arc::unsize(Arc::new(Square(4, &NUM_DROPS)), |s| s as &dyn Shape);
+        assert_eq!(y.area(), 16);
+
+        let w = Arc::downgrade(&y);
+        let ptr = Arc::into_raw(y);
+        let y = unsafe { Arc::from_raw(ptr) };
+        assert_eq!(w.upgrade().unwrap().area(), 16);
+
+        drop((x, y));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+        assert!(w.upgrade().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "coerce has to return the value it was given")]
+    fn test_unsize_other_value() {
+        static OTHER: (u64, u64) = (1, 2);
+        let _ = Arc::unsize(Arc::new((3u64, 4u64)), |_| &OTHER.1 as &dyn std::fmt::Debug);
+    }
 }
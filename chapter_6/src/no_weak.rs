@@ -0,0 +1,227 @@
+use crate::Arc;
+use std::{
+    hint::black_box,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+// the Arc from the start of the chapter: without Weak there is no alloc_count,
+// so dropping the last reference and get_mut only touch a single counter
+struct ArcData<T> {
+    ref_count: AtomicUsize,
+    data: T,
+}
+
+pub struct ArcNoWeak<T> {
+    pointer: NonNull<ArcData<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for ArcNoWeak<T> {}
+unsafe impl<T: Send + Sync> Sync for ArcNoWeak<T> {}
+
+#[allow(dead_code)]
+impl<T> ArcNoWeak<T> {
+    pub fn new(value: T) -> Self {
+        UniqueArc::new(value).share()
+    }
+
+    fn data(&self) -> &ArcData<T> {
+        unsafe { self.pointer.as_ref() }
+    }
+
+    pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
+        if arc.data().ref_count.load(Ordering::Acquire) == 1 {
+            // nothing else can clone it while we hold the only reference
+            unsafe { Some(&mut arc.pointer.as_mut().data) }
+        } else {
+            None
+        }
+    }
+
+    // turns the only reference back into a UniqueArc
+    pub fn try_unique(arc: Self) -> Result<UniqueArc<T>, Self> {
+        if arc.data().ref_count.load(Ordering::Acquire) == 1 {
+            let pointer = arc.pointer;
+            std::mem::forget(arc);
+            Ok(UniqueArc { pointer })
+        } else {
+            Err(arc)
+        }
+    }
+
+    pub fn strong_count(arc: &Self) -> usize {
+        arc.data().ref_count.load(Ordering::Acquire)
+    }
+
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.pointer == b.pointer
+    }
+}
+
+impl<T> Deref for ArcNoWeak<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data().data
+    }
+}
+
+impl<T> Clone for ArcNoWeak<T> {
+    fn clone(&self) -> Self {
+        if self.data().ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        ArcNoWeak {
+            pointer: self.pointer,
+        }
+    }
+}
+
+impl<T> Drop for ArcNoWeak<T> {
+    fn drop(&mut self) {
+        if self.data().ref_count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            unsafe { drop(Box::from_raw(self.pointer.as_ptr())) };
+        }
+    }
+}
+
+// an ArcNoWeak that hasn't been shared yet, so it can be mutated
+// freely without looking at the counter
+pub struct UniqueArc<T> {
+    pointer: NonNull<ArcData<T>>,
+}
+
+unsafe impl<T: Send> Send for UniqueArc<T> {}
+unsafe impl<T: Sync> Sync for UniqueArc<T> {}
+
+impl<T> UniqueArc<T> {
+    pub fn new(value: T) -> Self {
+        let data = Box::new(ArcData {
+            ref_count: AtomicUsize::new(1),
+            data: value,
+        });
+        UniqueArc {
+            pointer: NonNull::from(Box::leak(data)),
+        }
+    }
+
+    pub fn share(self) -> ArcNoWeak<T> {
+        let pointer = self.pointer;
+        std::mem::forget(self);
+        ArcNoWeak { pointer }
+    }
+}
+
+impl<T> Deref for UniqueArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &self.pointer.as_ref().data }
+    }
+}
+
+impl<T> DerefMut for UniqueArc<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut self.pointer.as_mut().data }
+    }
+}
+
+impl<T> Drop for UniqueArc<T> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.pointer.as_ptr())) };
+    }
+}
+
+// per iteration: new, clone, drop both
+//   Arc: fetch_add, fetch_sub, fetch_sub on data_count, fetch_sub on alloc_count
+//   ArcNoWeak: fetch_add, fetch_sub, fetch_sub
+// get_mut:
+//   Arc: compare_exchange and store on alloc_count, load of data_count
+//   ArcNoWeak: load
+pub fn bench() {
+    const N: usize = 10_000_000;
+
+    let start_time = SystemTime::now();
+    for i in 0..N {
+        let a = Arc::new(i);
+        black_box(a.clone());
+    }
+    println!("Arc clone and drop: {:?}", start_time.elapsed());
+
+    let start_time = SystemTime::now();
+    for i in 0..N {
+        let a = ArcNoWeak::new(i);
+        black_box(a.clone());
+    }
+    println!("ArcNoWeak clone and drop: {:?}", start_time.elapsed());
+
+    let mut a = Arc::new(0);
+    let start_time = SystemTime::now();
+    for _ in 0..N {
+        *Arc::get_mut(black_box(&mut a)).unwrap() += 1;
+    }
+    println!("Arc get_mut: {:?}", start_time.elapsed());
+
+    let mut a = ArcNoWeak::new(0);
+    let start_time = SystemTime::now();
+    for _ in 0..N {
+        *ArcNoWeak::get_mut(black_box(&mut a)).unwrap() += 1;
+    }
+    println!("ArcNoWeak get_mut: {:?}", start_time.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_arc_no_weak() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DetectDrop;
+
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let x = ArcNoWeak::new(("hello", DetectDrop));
+        let y = x.clone();
+        assert_eq!(ArcNoWeak::strong_count(&x), 2);
+
+        let t = thread::spawn(move || {
+            assert_eq!(x.0, "hello");
+        });
+        assert_eq!(y.0, "hello");
+        t.join().unwrap();
+
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(y);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_unique_arc() {
+        let mut unique = UniqueArc::new(Vec::new());
+        unique.push(1);
+        unique.push(2);
+
+        let mut x = unique.share();
+        let y = x.clone();
+        assert!(ArcNoWeak::get_mut(&mut x).is_none());
+
+        let Err(x) = ArcNoWeak::try_unique(x) else {
+            panic!("y still shares the value");
+        };
+        drop(y);
+
+        let mut unique = ArcNoWeak::try_unique(x).ok().unwrap();
+        unique.push(3);
+        assert_eq!(*unique, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    alloc::{alloc, dealloc, handle_alloc_error, Layout},
+    marker::PhantomData,
+    mem,
+    ptr::{self, NonNull},
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+// everything in front of the slice, the slice itself follows in the same allocation
+#[repr(C)]
+struct ThinArcData<H, T> {
+    data_count: AtomicUsize,
+    alloc_count: AtomicUsize,
+    header: H,
+    len: usize,
+    // zero sized, marks where the slice starts and makes the prefix aligned for T
+    slice: [T; 0],
+}
+
+impl<H, T> ThinArcData<H, T> {
+    fn layout(len: usize) -> Layout {
+        let prefix =
+            Layout::from_size_align(mem::offset_of!(Self, slice), mem::align_of::<Self>()).unwrap();
+        let (layout, offset) = prefix.extend(Layout::array::<T>(len).unwrap()).unwrap();
+        debug_assert_eq!(offset, mem::offset_of!(Self, slice));
+        layout.pad_to_align()
+    }
+}
+
+// an Arc for a header followed by a slice that keeps the slice length in the
+// allocation, so the pointer is a single word and fits in an AtomicPtr
+pub struct ThinArc<H, T> {
+    pointer: NonNull<ThinArcData<H, T>>,
+    _marker: PhantomData<ThinArcData<H, T>>,
+}
+
+pub struct ThinWeak<H, T> {
+    pointer: NonNull<ThinArcData<H, T>>,
+}
+
+unsafe impl<H: Send + Sync, T: Send + Sync> Send for ThinArc<H, T> {}
+unsafe impl<H: Send + Sync, T: Send + Sync> Sync for ThinArc<H, T> {}
+
+unsafe impl<H: Send + Sync, T: Send + Sync> Send for ThinWeak<H, T> {}
+unsafe impl<H: Send + Sync, T: Send + Sync> Sync for ThinWeak<H, T> {}
+
+#[allow(dead_code)]
+impl<H, T> ThinArc<H, T> {
+    pub fn new(header: H, items: impl IntoIterator<Item = T>) -> Self {
+        let mut items: Vec<T> = items.into_iter().collect();
+        let len = items.len();
+        let layout = ThinArcData::<H, T>::layout(len);
+
+        unsafe {
+            let ptr = alloc(layout) as *mut ThinArcData<H, T>;
+            if ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            ptr.write(ThinArcData {
+                data_count: AtomicUsize::new(1),
+                alloc_count: AtomicUsize::new(1),
+                header,
+                len,
+                slice: [],
+            });
+            let slice = ptr::addr_of_mut!((*ptr).slice) as *mut T;
+            ptr::copy_nonoverlapping(items.as_ptr(), slice, len);
+            // the elements now belong to the ThinArc, only the buffer is left to free
+            items.set_len(0);
+
+            ThinArc {
+                pointer: NonNull::new_unchecked(ptr),
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    fn data(&self) -> &ThinArcData<H, T> {
+        unsafe { self.pointer.as_ref() }
+    }
+
+    pub fn header(&self) -> &H {
+        &self.data().header
+    }
+
+    pub fn slice(&self) -> &[T] {
+        // go through the raw pointer, the slice is outside of ThinArcData itself
+        unsafe {
+            let slice = ptr::addr_of!((*self.pointer.as_ptr()).slice) as *const T;
+            std::slice::from_raw_parts(slice, self.data().len)
+        }
+    }
+
+    pub fn strong_count(arc: &Self) -> usize {
+        arc.data().data_count.load(Ordering::Acquire)
+    }
+
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.pointer == b.pointer
+    }
+
+    // leaks the ThinArc, the pointer has to be turned back with from_raw to release it
+    pub fn into_raw(arc: Self) -> *mut () {
+        let ptr = arc.pointer.as_ptr() as *mut ();
+        mem::forget(arc);
+        ptr
+    }
+
+    // SAFETY: ptr must come from ThinArc::into_raw with the same H and T
+    // and every from_raw must match an into_raw
+    pub unsafe fn from_raw(ptr: *mut ()) -> Self {
+        ThinArc {
+            pointer: NonNull::new_unchecked(ptr as *mut ThinArcData<H, T>),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn downgrade(arc: &Self) -> ThinWeak<H, T> {
+        if arc.data().alloc_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        ThinWeak {
+            pointer: arc.pointer,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<H, T> ThinWeak<H, T> {
+    fn data(&self) -> &ThinArcData<H, T> {
+        unsafe { self.pointer.as_ref() }
+    }
+
+    pub fn upgrade(&self) -> Option<ThinArc<H, T>> {
+        let mut n = self.data().data_count.load(Ordering::Relaxed);
+        loop {
+            if n == 0 {
+                return None;
+            }
+            assert!(n < usize::MAX);
+
+            match self.data().data_count.compare_exchange_weak(
+                n,
+                n + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(ThinArc {
+                        pointer: self.pointer,
+                        _marker: PhantomData,
+                    })
+                }
+                Err(e) => n = e,
+            }
+        }
+    }
+}
+
+impl<H, T> Clone for ThinArc<H, T> {
+    fn clone(&self) -> Self {
+        if self.data().data_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        ThinArc {
+            pointer: self.pointer,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<H, T> Clone for ThinWeak<H, T> {
+    fn clone(&self) -> Self {
+        if self.data().alloc_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        ThinWeak {
+            pointer: self.pointer,
+        }
+    }
+}
+
+impl<H, T> Drop for ThinArc<H, T> {
+    fn drop(&mut self) {
+        if self.data().data_count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+
+            let ptr = self.pointer.as_ptr();
+            unsafe {
+                let len = (*ptr).len;
+                ptr::drop_in_place(ptr::addr_of_mut!((*ptr).header));
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                    ptr::addr_of_mut!((*ptr).slice) as *mut T,
+                    len,
+                ));
+            }
+
+            // the last ThinArc releases the alloc_count shared by all ThinArcs
+            drop(ThinWeak {
+                pointer: self.pointer,
+            });
+        }
+    }
+}
+
+impl<H, T> Drop for ThinWeak<H, T> {
+    fn drop(&mut self) {
+        if self.data().alloc_count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            let layout = ThinArcData::<H, T>::layout(self.data().len);
+            unsafe { dealloc(self.pointer.as_ptr() as *mut u8, layout) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicPtr, thread};
+
+    #[test]
+    fn test_thin_arc() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct NumDrops(u8);
+
+        impl Drop for NumDrops {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        assert_eq!(mem::size_of::<ThinArc<u64, u8>>(), mem::size_of::<usize>());
+        assert_eq!(
+            mem::size_of::<Option<ThinArc<u64, u8>>>(),
+            mem::size_of::<usize>()
+        );
+
+        let x = ThinArc::new(NumDrops(0), (1..=3).map(NumDrops));
+        assert_eq!(x.header().0, 0);
+        assert_eq!(x.slice().iter().map(|n| n.0).collect::<Vec<_>>(), [1, 2, 3]);
+
+        let y = x.clone();
+        let w = ThinArc::downgrade(&x);
+        assert!(ThinArc::ptr_eq(&x, &y));
+        assert_eq!(ThinArc::strong_count(&x), 2);
+
+        thread::spawn(move || assert_eq!(y.slice().len(), 3))
+            .join()
+            .unwrap();
+        drop(x);
+
+        // the header and all the elements
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 4);
+        assert!(w.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_thin_arc_interned_str() {
+        // header aligned more strictly than the slice
+        let x = ThinArc::new(0xdead_beef_u128, "hello".bytes());
+        assert_eq!(*x.header(), 0xdead_beef);
+        assert_eq!(std::str::from_utf8(x.slice()), Ok("hello"));
+
+        let empty = ThinArc::<(), String>::new((), []);
+        assert!(empty.slice().is_empty());
+    }
+
+    #[test]
+    fn test_thin_arc_in_atomic_ptr() {
+        let x = ThinArc::new("v1", [1, 2, 3]);
+        let current = AtomicPtr::new(ThinArc::into_raw(x.clone()));
+
+        let next = ThinArc::new("v2", [4, 5]);
+        let old = current.swap(ThinArc::into_raw(next), Ordering::AcqRel);
+        let old = unsafe { ThinArc::<&str, i32>::from_raw(old) };
+        assert!(ThinArc::ptr_eq(&old, &x));
+        assert_eq!(ThinArc::strong_count(&x), 2);
+
+        let current = unsafe { ThinArc::<&str, i32>::from_raw(current.into_inner()) };
+        assert_eq!((*current.header(), current.slice()), ("v2", &[4, 5][..]));
+    }
+}
@@ -1,8 +1,9 @@
+mod rendezvous;
+
 use std::{
     cell::UnsafeCell,
     ops::{Deref, DerefMut},
     sync::atomic::{AtomicU32, AtomicUsize, Ordering},
-    u32,
 };
 
 use atomic_wait::{wait, wake_all, wake_one};
@@ -16,22 +17,26 @@ struct Mutex<T> {
     // 1 is locked, no threads waiting
     // 2 is locked, threads are waiting
     state: AtomicU32,
+    // threads blocked in lock, 2 only says there might be some
+    waiters: AtomicU32,
     data: UnsafeCell<T>,
 }
 
 unsafe impl<T> Sync for Mutex<T> where T: Send {}
 
+#[allow(dead_code)]
 impl<T> Mutex<T> {
     #[inline]
     pub fn new(data: T) -> Self {
         Mutex {
             state: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
             data: UnsafeCell::new(data),
         }
     }
 
     #[inline]
-    pub fn lock(&self) -> MutexGuard<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
         if self
             .state
             .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
@@ -61,9 +66,11 @@ impl<T> Mutex<T> {
             return;
         }
 
+        self.waiters.fetch_add(1, Ordering::Relaxed);
         while self.state.swap(2, Ordering::Acquire) != 0 {
             wait(&self.state, 2);
         }
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -71,6 +78,42 @@ struct MutexGuard<'a, T> {
     lock: &'a Mutex<T>,
 }
 
+#[allow(dead_code)]
+impl<T> MutexGuard<'_, T> {
+    // runs `f` without holding the lock and locks it again afterwards
+    pub fn unlocked<U>(guard: &mut Self, f: impl FnOnce() -> U) -> U {
+        // locks again even if `f` panics, the guard still gets dropped after all
+        struct Relock<'a, T>(&'a Mutex<T>);
+
+        impl<T> Drop for Relock<'_, T> {
+            fn drop(&mut self) {
+                // a thread we woke up sets 2 again once it finds the lock taken
+                if self
+                    .0
+                    .state
+                    .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+                {
+                    self.0.lock_contnded();
+                }
+            }
+        }
+
+        if guard.lock.state.swap(0, Ordering::Release) == 2 {
+            wake_one(&guard.lock.state);
+        }
+        let _relock = Relock(guard.lock);
+        f()
+    }
+
+    // hands the lock to a waiting thread first, does nothing if nobody is waiting
+    pub fn bump(guard: &mut Self) {
+        if guard.lock.waiters.load(Ordering::Relaxed) != 0 {
+            MutexGuard::unlocked(guard, std::thread::yield_now);
+        }
+    }
+}
+
 impl<T> Deref for MutexGuard<'_, T> {
     type Target = T;
 
@@ -103,6 +146,7 @@ struct RwLock<T> {
     data: UnsafeCell<T>,
 }
 
+#[allow(dead_code)]
 impl<T> RwLock<T> {
     pub fn new(value: T) -> Self {
         RwLock {
@@ -112,10 +156,10 @@ impl<T> RwLock<T> {
         }
     }
 
-    pub fn read(&self) -> ReadGuard<T> {
+    pub fn read(&self) -> ReadGuard<'_, T> {
         let mut s = self.state.load(Ordering::Relaxed);
         loop {
-            if s % 2 == 0 {
+            if s.is_multiple_of(2) {
                 assert!(s != u32::MAX - 2, "too many readers");
                 match self.state.compare_exchange_weak(
                     s,
@@ -135,7 +179,7 @@ impl<T> RwLock<T> {
         }
     }
 
-    pub fn write(&self) -> WriteGuard<T> {
+    pub fn write(&self) -> WriteGuard<'_, T> {
         let mut s = self.state.load(Ordering::Relaxed);
         loop {
             // see if we can aquire the lock
@@ -152,7 +196,7 @@ impl<T> RwLock<T> {
                 }
             }
             // try to block new readers if no writers are waiting
-            if s % 2 == 0 {
+            if s.is_multiple_of(2) {
                 match self
                     .state
                     .compare_exchange(s, s + 1, Ordering::Relaxed, Ordering::Relaxed)
@@ -228,6 +272,7 @@ struct CondVar {
     waiters: AtomicUsize,
 }
 
+#[allow(dead_code)]
 impl CondVar {
     pub fn new() -> Self {
         CondVar {
@@ -267,8 +312,55 @@ impl CondVar {
 
 #[cfg(test)]
 mod tests {
-    use super::{CondVar, Mutex};
-    use std::{thread, time::Duration};
+    use super::{CondVar, Mutex, MutexGuard};
+    use std::{sync::atomic::Ordering, thread, time::Duration};
+
+    #[test]
+    fn mutex_unlocked() {
+        let m = Mutex::new(0);
+        let mut guard = m.lock();
+        *guard += 1;
+        thread::scope(|s| {
+            MutexGuard::unlocked(&mut guard, || {
+                s.spawn(|| *m.lock() += 1).join().unwrap();
+            });
+        });
+        *guard += 1;
+        drop(guard);
+        assert_eq!(*m.lock(), 3);
+    }
+
+    #[test]
+    fn mutex_bump() {
+        let m = Mutex::new(Vec::new());
+
+        // uncontended, bump doesn't touch the lock
+        let mut guard = m.lock();
+        MutexGuard::bump(&mut guard);
+        assert_eq!(m.state.load(Ordering::Relaxed), 1);
+        // not even after unlocked, relocking doesn't make up waiters
+        MutexGuard::unlocked(&mut guard, || {});
+        assert_eq!(m.state.load(Ordering::Relaxed), 1);
+        MutexGuard::bump(&mut guard);
+        assert_eq!(m.state.load(Ordering::Relaxed), 1);
+        assert_eq!(m.waiters.load(Ordering::Relaxed), 0);
+        drop(guard);
+
+        let mut guard = m.lock();
+        thread::scope(|s| {
+            s.spawn(|| m.lock().push(1));
+            while m.waiters.load(Ordering::Relaxed) == 0 {
+                std::hint::spin_loop();
+            }
+            // keep bumping until the waiting thread had its turn
+            while guard.is_empty() {
+                MutexGuard::bump(&mut guard);
+            }
+            guard.push(2);
+            drop(guard);
+        });
+        assert_eq!(*m.lock(), [1, 2]);
+    }
 
     #[test]
     fn cond_vars() {
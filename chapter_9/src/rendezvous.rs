@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use crate::{CondVar, Mutex};
+
+struct State<T> {
+    // the value a sender is currently handing over
+    message: Option<T>,
+    // number of values put in and taken out of the slot so far
+    offered: u64,
+    taken: u64,
+    senders: usize,
+    receivers: usize,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    changed: CondVar,
+}
+
+// zero capacity channel, every send waits until a receiver has taken the value
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+#[allow(dead_code)]
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            message: None,
+            offered: 0,
+            taken: 0,
+            senders: 1,
+            receivers: 1,
+        }),
+        changed: CondVar::new(),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[allow(dead_code)]
+impl<T> Sender<T> {
+    // hands the value back if every receiver is gone before it was taken
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.state.lock();
+
+        // wait for the slot, another sender may be in the middle of a handoff
+        while state.message.is_some() && state.receivers > 0 {
+            state = self.shared.changed.wait(state);
+        }
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+
+        state.message = Some(value);
+        state.offered += 1;
+        let ticket = state.offered;
+        self.shared.changed.notify_all();
+
+        while state.taken < ticket {
+            if state.receivers == 0 {
+                // nobody took it, so it is still ours
+                return Err(SendError(state.message.take().unwrap()));
+            }
+            state = self.shared.changed.wait(state);
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl<T> Receiver<T> {
+    // fails once every sender is gone and there is nothing left to take
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(value) = state.message.take() {
+                state.taken += 1;
+                self.shared.changed.notify_all();
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self.shared.changed.wait(state);
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().senders -= 1;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().receivers -= 1;
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_lockstep_handoff() {
+        let (sender, receiver) = channel();
+        let sent = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            // owned by the scope, so a failed assert drops it and the sender gets an error
+            let receiver = receiver;
+            s.spawn(|| {
+                for n in 0..5 {
+                    sender.send(n).unwrap();
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+
+            for n in 0..5 {
+                // the sender can't get ahead of us
+                assert!(sent.load(Ordering::Relaxed) <= n);
+                assert_eq!(receiver.recv(), Ok(n));
+            }
+        });
+    }
+
+    #[test]
+    fn test_many_senders_and_receivers() {
+        let (sender, receiver) = channel();
+        let received = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let sender = sender.clone();
+                s.spawn(move || {
+                    for n in 0..100 {
+                        sender.send(n).unwrap();
+                    }
+                });
+            }
+            drop(sender);
+
+            for _ in 0..4 {
+                let receiver = receiver.clone();
+                let received = &received;
+                s.spawn(move || {
+                    while receiver.recv().is_ok() {
+                        received.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(received.load(Ordering::Relaxed), 400);
+    }
+
+    #[test]
+    fn test_disconnected() {
+        let (sender, receiver) = channel::<i32>();
+        drop(sender);
+        assert_eq!(receiver.recv(), Err(RecvError));
+
+        let (sender, receiver) = channel();
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                drop(receiver);
+            });
+            // the value comes back once the receiver gives up
+            assert_eq!(sender.send("hello"), Err(SendError("hello")));
+        });
+    }
+}
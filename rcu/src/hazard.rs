@@ -0,0 +1,210 @@
+use std::{
+    cell::RefCell,
+    ptr,
+    sync::{
+        atomic::{fence, AtomicPtr, Ordering},
+        Mutex,
+    },
+};
+
+pub struct HazardRecord {
+    pub hazard: AtomicPtr<()>,
+}
+
+// records of every thread that ever protected a pointer, shared by all domains
+// so a thread is registered exactly once no matter how many structures it reads
+pub(crate) static REGISTRY: Mutex<Vec<&'static HazardRecord>> = Mutex::new(Vec::new());
+
+fn new_record() -> &'static HazardRecord {
+    let record = Box::leak(Box::new(HazardRecord {
+        hazard: AtomicPtr::new(ptr::null_mut()),
+    }));
+    REGISTRY.lock().expect("Lock poisoned").push(record);
+    record
+}
+
+// records of threads that exited, handed out again before allocating new ones
+static FREE_RECORDS: Mutex<Vec<&'static HazardRecord>> = Mutex::new(Vec::new());
+
+// records the current thread isn't using right now
+struct LocalRecords(RefCell<Vec<&'static HazardRecord>>);
+
+impl Drop for LocalRecords {
+    fn drop(&mut self) {
+        FREE_RECORDS
+            .lock()
+            .expect("Lock poisoned")
+            .append(self.0.get_mut());
+    }
+}
+
+thread_local! {
+    static LOCAL_RECORDS: LocalRecords = const { LocalRecords(RefCell::new(Vec::new())) };
+}
+
+fn protect_with<T>(record: &HazardRecord, src: &AtomicPtr<T>) -> *mut T {
+    let mut ptr = src.load(Ordering::Relaxed);
+    loop {
+        // release, so whatever we read through the previous hazard
+        // happens before a scan that no longer sees it frees it
+        record.hazard.store(ptr as *mut (), Ordering::Release);
+        // pairs with the fence in get_hazard_pointers
+        fence(Ordering::SeqCst);
+        let current = src.load(Ordering::Acquire);
+        if current == ptr {
+            // the address may have been freed and reused in between,
+            // only the pointer we just loaded belongs to the current value
+            return current;
+        }
+        ptr = current;
+    }
+}
+
+// a hazard record borrowed for as long as this lives, so every guard and every
+// operation protects its pointer in its own record and nobody else clears it
+pub struct HazardPointer {
+    pub(crate) record: &'static HazardRecord,
+}
+
+impl HazardPointer {
+    pub fn new() -> Self {
+        let record = LOCAL_RECORDS
+            .try_with(|local| local.0.borrow_mut().pop())
+            .ok()
+            .flatten()
+            .or_else(|| FREE_RECORDS.lock().expect("Lock poisoned").pop())
+            .unwrap_or_else(new_record);
+        HazardPointer { record }
+    }
+
+    // publishes the pointer stored in `src` as our hazard.
+    // the pointer is only safe to use if it is still in `src` after publishing,
+    // otherwise it might have been retired before the scanning thread saw our hazard
+    pub fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        protect_with(self.record, src)
+    }
+
+    pub fn clear(&self) {
+        self.record.hazard.store(ptr::null_mut(), Ordering::Release);
+    }
+}
+
+impl Default for HazardPointer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HazardPointer {
+    fn drop(&mut self) {
+        self.clear();
+        let record = self.record;
+        if LOCAL_RECORDS
+            .try_with(|local| local.0.borrow_mut().push(record))
+            .is_err()
+        {
+            // the thread is exiting, let another thread have it
+            FREE_RECORDS.lock().expect("Lock poisoned").push(record);
+        }
+    }
+}
+
+// get a snapshot of all published hazard pointers accros threads
+pub fn get_hazard_pointers() -> Vec<*mut ()> {
+    // a pointer unlinked before this fence is either in this snapshot
+    // or the reader trying to protect it sees that it is gone
+    fence(Ordering::SeqCst);
+    let registry = REGISTRY.lock().expect("Lock poisoned");
+    registry
+        .iter()
+        .map(|record| record.hazard.load(Ordering::Acquire))
+        .filter(|&ptr| !ptr.is_null())
+        .collect()
+}
+
+pub(crate) struct Retired {
+    ptr: *mut (),
+    drop: unsafe fn(*mut ()),
+}
+
+// SAFETY: retired pointers are no longer reachable from the structure,
+// the only thing left to do with them is to free them on whatever thread scans
+unsafe impl Send for Retired {}
+
+unsafe fn drop_box<T>(ptr: *mut ()) {
+    drop(Box::from_raw(ptr as *mut T));
+}
+
+// keeps the pointers a structure unlinked until no thread has them as a hazard
+pub struct HazardDomain {
+    pub(crate) retired_list: Mutex<Vec<Retired>>,
+}
+
+impl HazardDomain {
+    pub const fn new() -> Self {
+        HazardDomain {
+            retired_list: Mutex::new(Vec::new()),
+        }
+    }
+
+    // retires a pointer by adding it to the retired list
+    //
+    // SAFETY: the pointer has to come from a Box, be unreachable for new readers
+    // and be retired only once
+    pub unsafe fn retire<T>(&self, ptr: *mut T) {
+        let len = {
+            let mut retired = self.retired_list.lock().expect("Lock poisoned");
+            retired.push(Retired {
+                ptr: ptr as *mut (),
+                drop: drop_box::<T>,
+            });
+            retired.len()
+        };
+
+        // set an arbitrary value for now, should be configurable
+        if len >= 10 {
+            self.scan_and_reclaim();
+        }
+    }
+
+    // frees every retired pointer that is not protected by some thread
+    pub fn scan_and_reclaim(&self) {
+        // take the list before looking at the hazards, a pointer retired after
+        // the snapshot could be protected by a thread the snapshot missed
+        let retired = std::mem::take(&mut *self.retired_list.lock().expect("Lock poisoned"));
+        let hazards = get_hazard_pointers();
+        let (protected, reclaimed): (Vec<_>, Vec<_>) =
+            retired.into_iter().partition(|r| hazards.contains(&r.ptr));
+        self.retired_list
+            .lock()
+            .expect("Lock poisoned")
+            .extend(protected);
+
+        // dropping a value might retire something else, so not under the lock
+        for r in reclaimed {
+            // SAFETY: the pointer was created from a box and nobody protects it
+            unsafe { (r.drop)(r.ptr) };
+        }
+    }
+}
+
+impl Default for HazardDomain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HazardDomain {
+    fn drop(&mut self) {
+        // the structure owning the domain is gone, so nobody can be reading
+        // the retired pointers anymore, even if a stale hazard says so
+        for r in self
+            .retired_list
+            .get_mut()
+            .expect("Lock poisoned")
+            .drain(..)
+        {
+            unsafe { (r.drop)(r.ptr) };
+        }
+    }
+}
@@ -1,165 +1,68 @@
+mod hazard;
+mod ms_queue;
+mod treiber_stack;
+mod watch;
+
 use std::{
     marker::PhantomData,
     ops::Deref,
-    ptr,
-    sync::{
-        atomic::{AtomicPtr, Ordering},
-        Mutex,
-    },
+    sync::atomic::{AtomicPtr, Ordering},
 };
 
+use hazard::{HazardDomain, HazardPointer};
+
 fn main() {
     println!("Hello, world!");
 }
 
-pub struct HazardRecord {
-    pub hazard: AtomicPtr<()>,
-}
-
-// SAFETY: We guarantee that HazardRecordPtr pointers are safely shared across threads.
-// This safety guarantee is your responsibility as the programmer.
-unsafe impl Send for HazardRecord {}
-unsafe impl Sync for HazardRecord {}
-
-#[derive(Clone, Copy)]
-struct Ptr(*mut ());
-
-// SAFETY: We guarantee that Ptr pointers are safely shared across threads.
-// This safety guarantee is your responsibility as the programmer.
-unsafe impl Send for Ptr {}
-unsafe impl Sync for Ptr {}
-
-static UNINITIALIZED_FLAG: u8 = 0;
-
-thread_local! {
-    static HAZARD_RECORD: &'static HazardRecord = {
-        let record = Box::new(HazardRecord {
-            hazard: AtomicPtr::new(UNINITIALIZED_FLAG as *mut ()),
-        });
-        let record_ref = Box::leak(record);
-        record_ref
-    }
-}
-
 struct Rcu<T> {
     ptr: AtomicPtr<T>,
-    registry: Mutex<Vec<&'static HazardRecord>>,
-    retired_list: Mutex<Vec<Ptr>>,
+    domain: HazardDomain,
 }
 
+#[allow(dead_code)]
 impl<T> Rcu<T> {
     pub fn new(value: T) -> Self {
         Rcu {
             ptr: AtomicPtr::new(Box::into_raw(Box::new(value))),
-            registry: Mutex::new(Vec::new()),
-            retired_list: Mutex::new(Vec::new()),
+            domain: HazardDomain::new(),
         }
     }
 
     pub fn read(&self) -> ReadGuard<'_, T> {
-        loop {
-            let ptr = self.ptr.load(Ordering::Acquire);
-            if ptr.is_null() {
-                panic!("Failed to read pointer, poitner cannot be null");
-            }
-            if HAZARD_RECORD
-                .try_with(|record| record.hazard.load(Ordering::Acquire))
-                .unwrap()
-                == UNINITIALIZED_FLAG as *mut ()
-            {
-                // set the hazard pointer to global registry
-                self.set_hazard(ptr);
-                HAZARD_RECORD
-                    .with(|hazard| self.registry.lock().expect("Lock poisoned").push(&hazard));
-            } else {
-                self.set_hazard(ptr);
-            }
-            // check again to make sure it didnt change
-            if self.ptr.load(Ordering::Acquire) == ptr {
-                return ReadGuard {
-                    ptr,
-                    rcu: self,
-                    _marker: PhantomData,
-                };
-            }
+        let hazard = HazardPointer::new();
+        let ptr = hazard.protect(&self.ptr);
+        if ptr.is_null() {
+            panic!("Failed to read pointer, poitner cannot be null");
+        }
+        ReadGuard {
+            ptr,
+            _hazard: hazard,
+            _marker: PhantomData,
         }
     }
 
     pub fn write(&self, value: T) {
         let new_ptr = Box::into_raw(Box::new(value));
         let old_ptr = self.ptr.swap(new_ptr, Ordering::AcqRel);
-        // retire the old pointer to the global retired list
-        self.retire(old_ptr as *mut ());
-    }
-
-    // sets a hazard pointer to the current thread local storage
-    fn set_hazard(&self, ptr: *mut T) {
-        HAZARD_RECORD.with(|record| {
-            record.hazard.store(ptr as *mut (), Ordering::Release);
-        });
-    }
-
-    // clears a hazard pointer from the current thread local storage
-    fn clear_hazard(&self) {
-        HAZARD_RECORD.with(|record| {
-            record.hazard.store(ptr::null_mut(), Ordering::Release);
-        });
-    }
-
-    // get a snapshot of all published hazard pointers accros threads
-    fn get_hazard_pointers(&self) -> Vec<*mut ()> {
-        let registry = self.registry.lock().expect("Lock poisoned");
-        registry
-            .iter()
-            .map(|record_ptr| record_ptr.hazard.load(Ordering::Acquire))
-            .filter(|&ptr| !ptr.is_null())
-            .collect()
-    }
-
-    // retires a pointer by adding it to the global retired list
-    fn retire(&self, ptr: *mut ()) {
-        let mut retired = self.retired_list.lock().expect("Lock poisoned");
-        retired.push(Ptr(ptr));
-
-        // set an arbitrary value for now, should be configurable
-        if retired.len() >= 10 {
-            self.scan_and_reclaim();
-        }
-    }
-
-    // clears out pointers out of retired lisr periodically
-    fn scan_and_reclaim(&self) {
-        let hazards = self.get_hazard_pointers();
-        let mut retired = self.retired_list.lock().expect("Lock poisoned");
-
-        let mut i = 0;
-        while i < retired.len() {
-            let Ptr(ptr) = retired[i];
-            if !hazards.iter().any(|&raw| raw == ptr) {
-                let Ptr(old) = retired.swap_remove(i);
-                // SAFETY: pointer was created from a boxed value
-                unsafe { drop(Box::from_raw(old)) };
-            } else {
-                i += 1;
-            }
-        }
+        // SAFETY: the old pointer came from a box and new readers can only see new_ptr
+        unsafe { self.domain.retire(old_ptr) };
     }
 }
 
 impl<T> Drop for Rcu<T> {
     fn drop(&mut self) {
-        // clear out the retired list so we will not leak memory
-        self.scan_and_reclaim();
         // SAFETY: we are dropping the RCU, means ther are no longer any readers
         // so the value is safe to drop. Pointer was created from a box so we need
-        // to box it again to drop it.
-        drop(unsafe { Box::from_raw(self.ptr.load(Ordering::Acquire)) });
+        // to box it again to drop it. The domain frees the retired values.
+        drop(unsafe { Box::from_raw(*self.ptr.get_mut()) });
     }
 }
 
 struct ReadGuard<'a, T> {
     ptr: *const T,
-    rcu: &'a Rcu<T>,
+    // clears the hazard when the guard is dropped
+    _hazard: HazardPointer,
     _marker: PhantomData<&'a T>,
 }
 
@@ -167,29 +70,18 @@ impl<T> Deref for ReadGuard<'_, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         // SAFETY: we know that if we obtained the read guard then the pointer is not null
-        // and was inserted in to the global hazard registry, so it is safe to dereference
+        // and is protected by the hazard of this guard, so it is safe to dereference
         unsafe { &*self.ptr }
     }
 }
 
-impl<T> Drop for ReadGuard<'_, T> {
-    fn drop(&mut self) {
-        // clear the hazard pointer
-        self.rcu.clear_hazard();
-    }
-}
-
 #[cfg(test)]
 mod tests {
 
-    use std::{
-        rc,
-        sync::Arc,
-        thread::{self, sleep},
-        time::Duration,
-    };
+    use std::{ptr, sync::Arc, thread, time::Duration};
 
     use super::*;
+    use crate::hazard::get_hazard_pointers;
 
     #[test]
     fn test_rcu_basic() {
@@ -213,31 +105,28 @@ mod tests {
         let rcu = Rcu::new(10);
         let record = rcu.read();
 
-        let hazards = rcu.get_hazard_pointers();
+        let ptr = &*record as *const i32 as *mut ();
+
+        let hazards = get_hazard_pointers();
         assert!(
-            !hazards.is_empty(),
-            "Hazard pointer registry unexpectedly empty!"
+            hazards.contains(&ptr),
+            "Hazard pointer registry doesnt contain the read value!"
         );
+        // other tests run at the same time, only look at our own hazard
+        let val = unsafe { *(ptr as *mut i32) };
+        assert_eq!(val, 10, "Expected pointer value to be 10, but got {}", val);
 
-        for &ptr in &hazards {
-            let val = unsafe { *(ptr as *mut i32) };
-            println!("Hazard pointer points to: {}", val);
-            assert_eq!(val, 10, "Expected pointer value to be 10, but got {}", val);
-        }
         // Clear hazard pointer
+        let hazard_record = record._hazard.record;
         drop(record);
 
-        let hazards = rcu.get_hazard_pointers();
-        for &ptr in &hazards {
-            assert!(ptr.is_null(), "Pointer has not been cleared");
-        }
+        let hazards = get_hazard_pointers();
+        assert!(!hazards.contains(&ptr), "Pointer has not been cleared");
 
-        HAZARD_RECORD.with(|record| {
-            assert!(
-                record.hazard.load(Ordering::Acquire).is_null(),
-                "Thread local hazard pointer is not null.",
-            )
-        });
+        assert!(
+            hazard_record.hazard.load(Ordering::Acquire).is_null(),
+            "Hazard record of the guard is not null.",
+        );
     }
 
     #[test]
@@ -250,40 +139,50 @@ mod tests {
 
         // pointer should be in the retired list now
         assert!(
-            !rcu.retired_list.lock().expect("lock poisoned").is_empty(),
+            !rcu.domain
+                .retired_list
+                .lock()
+                .expect("lock poisoned")
+                .is_empty(),
             "Retired list is unexpectedly empty"
         );
 
         // if no pointer is in hazard list then it should be reclaimed
-        rcu.scan_and_reclaim();
+        rcu.domain.scan_and_reclaim();
 
         assert!(
-            rcu.retired_list.lock().expect("lock poisoned").is_empty(),
+            rcu.domain
+                .retired_list
+                .lock()
+                .expect("lock poisoned")
+                .is_empty(),
             "Pointer has not been reclaimed from the hazard list"
         );
     }
 
     #[test]
-    fn test_one_hazard_per_thread() {
+    fn test_hazard_per_guard() {
         let rcu = Rcu::new(10);
-        // reading the value will push a pointer to a hazard list
         let value_1 = rcu.read();
+        let value_2 = rcu.read();
 
+        // every guard protects its value in its own record
         assert!(
-            rcu.registry.lock().expect("lock poinsoned").len() == 1,
-            "Should contain only one record per thread"
+            !ptr::eq(value_1._hazard.record, value_2._hazard.record),
+            "Guards should not share a hazard record"
         );
 
-        // second read should update exisitng hazard record
-        let value_2 = rcu.read();
-
+        // the record goes back to the thread when the guard is dropped
+        let record = value_2._hazard.record;
+        drop(value_2);
+        let value_3 = rcu.read();
         assert!(
-            rcu.registry.lock().expect("lock poinsoned").len() == 1,
-            "Should contain only one record per thread"
+            ptr::eq(value_3._hazard.record, record),
+            "Hazard record should be reused"
         );
 
         drop(value_1);
-        drop(value_2);
+        drop(value_3);
     }
 
     #[test]
@@ -308,7 +207,7 @@ mod tests {
             rcu.write(20);
         });
         assert!(
-            rcu.retired_list.lock().expect("lock poisoned").len() == 1,
+            rcu.domain.retired_list.lock().expect("lock poisoned").len() == 1,
             "Doesnt have exatrly 1 element in retired list"
         )
     }
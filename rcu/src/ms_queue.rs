@@ -0,0 +1,292 @@
+use std::{
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::hazard::{HazardDomain, HazardPointer};
+
+struct Node<T> {
+    // uninitialized in the dummy node, and again once pop moved it out
+    value: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(value: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            value,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+// Michael-Scott lock-free queue, a non blocking alternative to the Mutex<VecDeque>
+// channel. head always points at a dummy node, the first value lives in the node
+// after it. popping takes the value out of that node and makes it the new dummy,
+// so a pop needs both head and next as hazards
+pub struct MsQueue<T> {
+    head: AtomicPtr<Node<T>>,
+    // either the last node or the one before it, if a push hasn't caught up yet
+    tail: AtomicPtr<Node<T>>,
+    domain: HazardDomain,
+    _marker: PhantomData<T>,
+}
+
+// SAFETY: values are only ever moved in and out, never shared between threads
+unsafe impl<T: Send> Send for MsQueue<T> {}
+unsafe impl<T: Send> Sync for MsQueue<T> {}
+
+#[allow(dead_code)]
+impl<T> MsQueue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::new(MaybeUninit::uninit());
+        MsQueue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            domain: HazardDomain::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = Node::new(MaybeUninit::new(value));
+        let hazard = HazardPointer::new();
+        loop {
+            let tail = hazard.protect(&self.tail);
+            // SAFETY: tail is our hazard so it can't be freed under us
+            let next = unsafe { &(*tail).next };
+            match next.compare_exchange(ptr::null_mut(), node, Ordering::Release, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    // fine if it fails, somebody else already moved tail along
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        node,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                    return;
+                }
+                Err(next) => {
+                    // help the push that is lagging behind before trying again
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        next,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let head_hazard = HazardPointer::new();
+        let next_hazard = HazardPointer::new();
+        loop {
+            let head = head_hazard.protect(&self.head);
+            // SAFETY: head is our hazard so it can't be freed under us
+            let next = next_hazard.protect(unsafe { &(*head).next });
+            // next only gets retired after head moved past it twice,
+            // so if head is still the same our second hazard came in time
+            if self.head.load(Ordering::Acquire) != head {
+                continue;
+            }
+            if next.is_null() {
+                return None;
+            }
+            // never let head pass tail, tail has to point to a node that isn't retired
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                // SAFETY: next is the new dummy, only the thread that moved head
+                // onto it takes its value and nobody else ever reads it
+                let value = unsafe { (*next).value.assume_init_read() };
+                drop((head_hazard, next_hazard));
+                // SAFETY: the old dummy came from a box and can't be reached from head anymore
+                unsafe { self.domain.retire(head) };
+                return Some(value);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let hazard = HazardPointer::new();
+        let head = hazard.protect(&self.head);
+        // SAFETY: head is our hazard so it can't be freed under us
+        unsafe { (*head).next.load(Ordering::Acquire) }.is_null()
+    }
+}
+
+impl<T> Default for MsQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for MsQueue<T> {
+    fn drop(&mut self) {
+        // SAFETY: we own the queue, so the nodes still in it are only ours,
+        // everything after the dummy holds a value
+        let dummy = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut node = dummy.next.load(Ordering::Relaxed);
+        while !node.is_null() {
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { boxed.value.assume_init_drop() };
+            node = boxed.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rcu;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    struct DetectDrop<'a>(&'a AtomicUsize);
+
+    impl Drop for DetectDrop<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let queue = MsQueue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+
+        for i in 0..5 {
+            queue.push(i);
+        }
+        assert!(!queue.is_empty());
+        for i in 0..5 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+
+        // the queue keeps working after running empty
+        queue.push(5);
+        assert_eq!(queue.pop(), Some(5));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let queue = MsQueue::new();
+        for _ in 0..20 {
+            queue.push(DetectDrop(&NUM_DROPS));
+        }
+        // popped values are dropped by the caller, not again with the node
+        for _ in 0..15 {
+            drop(queue.pop());
+        }
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 15);
+
+        drop(queue);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 20);
+    }
+
+    #[test]
+    fn test_mpmc() {
+        let iterations = if cfg!(miri) { 50 } else { 10_000 };
+        let producers = 2;
+        let queue = MsQueue::new();
+        let received = AtomicUsize::new(0);
+
+        let popped: Vec<Vec<(usize, usize)>> = thread::scope(|s| {
+            for t in 0..producers {
+                let queue = &queue;
+                s.spawn(move || {
+                    for i in 0..iterations {
+                        queue.push((t, i));
+                    }
+                });
+            }
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut popped = Vec::new();
+                        while received.load(Ordering::Relaxed) < producers * iterations {
+                            match queue.pop() {
+                                Some(value) => {
+                                    popped.push(value);
+                                    received.fetch_add(1, Ordering::Relaxed);
+                                }
+                                None => thread::yield_now(),
+                            }
+                        }
+                        popped
+                    })
+                })
+                .collect();
+            consumers.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // every consumer sees the values of each producer in the order they were pushed
+        for popped in &popped {
+            for t in 0..producers {
+                let values: Vec<_> = popped.iter().filter(|v| v.0 == t).map(|v| v.1).collect();
+                assert!(values.windows(2).all(|w| w[0] < w[1]));
+            }
+        }
+
+        // and every value comes out exactly once
+        let mut all: Vec<_> = popped.into_iter().flatten().collect();
+        all.sort();
+        let expected: Vec<_> = (0..producers)
+            .flat_map(|t| (0..iterations).map(move |i| (t, i)))
+            .collect();
+        assert_eq!(all, expected);
+        assert!(queue.is_empty());
+        // with all threads gone nothing is protected anymore
+        queue.domain.scan_and_reclaim();
+        assert!(queue.domain.retired_list.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_push_pop_while_reading() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let rcu = Rcu::new(DetectDrop(&NUM_DROPS));
+        let queue = MsQueue::new();
+
+        let guard = rcu.read();
+        // neither of the queue's hazards may take away the one of the guard
+        queue.push(1);
+        assert!(!queue.is_empty());
+        assert_eq!(queue.pop(), Some(1));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..20 {
+                    rcu.write(DetectDrop(&NUM_DROPS));
+                }
+                rcu.domain.scan_and_reclaim();
+            });
+        });
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 19);
+
+        drop(guard);
+        rcu.domain.scan_and_reclaim();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 20);
+    }
+}
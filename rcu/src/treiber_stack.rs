@@ -0,0 +1,259 @@
+use std::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::hazard::{HazardDomain, HazardPointer};
+
+struct Node<T> {
+    // moved out by pop, the node itself is freed later when no thread protects it
+    value: ManuallyDrop<T>,
+    // never changes once the node is pushed
+    next: *mut Node<T>,
+}
+
+// lock-free stack, popped nodes are retired and only freed once no thread
+// has them as its hazard, so a node can't be reused while somebody is
+// still about to compare_exchange it, which is what rules out ABA
+pub struct TreiberStack<T> {
+    head: AtomicPtr<Node<T>>,
+    domain: HazardDomain,
+    _marker: PhantomData<T>,
+}
+
+// SAFETY: values are only ever moved in and out, never shared between threads
+unsafe impl<T: Send> Send for TreiberStack<T> {}
+unsafe impl<T: Send> Sync for TreiberStack<T> {}
+
+#[allow(dead_code)]
+impl<T> TreiberStack<T> {
+    pub const fn new() -> Self {
+        TreiberStack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            domain: HazardDomain::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = Box::into_raw(Box::new(Node {
+            value: ManuallyDrop::new(value),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: nobody else sees the node until the exchange succeeds
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(e) => head = e,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let hazard = HazardPointer::new();
+        loop {
+            let head = hazard.protect(&self.head);
+            if head.is_null() {
+                return None;
+            }
+            // SAFETY: head is our hazard so it can't be freed under us
+            let next = unsafe { (*head).next };
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                drop(hazard);
+                // SAFETY: only the thread that unlinked the node takes the value,
+                // other threads may still read `next` but never touch `value`
+                let value = unsafe { ManuallyDrop::take(&mut (*head).value) };
+                // SAFETY: the node came from a box and can't be reached from head anymore
+                unsafe { self.domain.retire(head) };
+                return Some(value);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Default for TreiberStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            // SAFETY: we own the stack, so the nodes still on it are only ours
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { ManuallyDrop::drop(&mut boxed.value) };
+            node = boxed.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rcu;
+    use std::{
+        collections::HashSet,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    struct DetectDrop<'a>(&'a AtomicUsize);
+
+    impl Drop for DetectDrop<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let stack = TreiberStack::new();
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+
+        for i in 0..5 {
+            stack.push(i);
+        }
+        for i in (0..5).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let stack = TreiberStack::new();
+        for _ in 0..20 {
+            stack.push(DetectDrop(&NUM_DROPS));
+        }
+        // popped values are dropped by the caller, not again with the node
+        for _ in 0..15 {
+            drop(stack.pop());
+        }
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 15);
+        // some nodes are still retired, their values are gone already
+        assert!(!stack.domain.retired_list.lock().unwrap().is_empty());
+
+        drop(stack);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 20);
+    }
+
+    #[test]
+    fn test_push_pop_threads() {
+        let iterations = if cfg!(miri) { 50 } else { 10_000 };
+        let stack = TreiberStack::new();
+
+        let popped: Vec<Vec<usize>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let stack = &stack;
+                    s.spawn(move || {
+                        let mut popped = Vec::new();
+                        for i in 0..iterations {
+                            stack.push(t * iterations + i);
+                            if i % 2 == 0 {
+                                popped.extend(stack.pop());
+                            }
+                        }
+                        popped
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // every value comes out exactly once
+        let mut seen = HashSet::new();
+        for value in popped.into_iter().flatten() {
+            assert!(seen.insert(value));
+        }
+        while let Some(value) = stack.pop() {
+            assert!(seen.insert(value));
+        }
+        assert_eq!(seen.len(), 4 * iterations);
+        // with all threads gone nothing is protected anymore
+        stack.domain.scan_and_reclaim();
+        assert!(stack.domain.retired_list.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_aba() {
+        // a tiny stack where every thread pops and pushes the same values back,
+        // the allocator keeps handing out the addresses of freed nodes so without
+        // hazards a stale head would often compare equal to a new one
+        let iterations = if cfg!(miri) { 50 } else { 20_000 };
+        let stack = TreiberStack::new();
+        for i in 0..3 {
+            stack.push(Box::new(i));
+        }
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..iterations {
+                        let a = stack.pop();
+                        let b = stack.pop();
+                        for value in [a, b].into_iter().flatten() {
+                            stack.push(value);
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut values = Vec::new();
+        while let Some(value) = stack.pop() {
+            values.push(*value);
+        }
+        values.sort();
+        assert_eq!(values, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_pop_while_reading() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let rcu = Rcu::new(DetectDrop(&NUM_DROPS));
+        let stack = TreiberStack::new();
+        stack.push(1);
+
+        let guard = rcu.read();
+        // popping must not take away the hazard of the guard
+        assert_eq!(stack.pop(), Some(1));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..20 {
+                    rcu.write(DetectDrop(&NUM_DROPS));
+                }
+                rcu.domain.scan_and_reclaim();
+            });
+        });
+        // everything the writer replaced is gone, except what the guard still reads
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 19);
+
+        drop(guard);
+        rcu.domain.scan_and_reclaim();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 20);
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use atomic_wait::{wait, wake_all};
+
+use crate::{ReadGuard, Rcu};
+
+struct Shared<T> {
+    value: Rcu<T>,
+    // bumped by 2 on every send, the lowest bit is set once the sender is gone
+    version: AtomicU32,
+}
+
+const CLOSED: u32 = 1;
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // last version this receiver has seen, without the CLOSED bit
+    seen: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+#[allow(dead_code)]
+pub fn channel<T>(value: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        value: Rcu::new(value),
+        version: AtomicU32::new(0),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, seen: 0 },
+    )
+}
+
+#[allow(dead_code)]
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) {
+        self.shared.value.write(value);
+        self.shared.version.fetch_add(2, Ordering::Release);
+        wake_all(&self.shared.version);
+    }
+
+    pub fn borrow(&self) -> ReadGuard<'_, T> {
+        self.shared.value.read()
+    }
+
+    // the new receiver treats the current value as already seen
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.shared.version.load(Ordering::Acquire) & !CLOSED,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.version.fetch_or(CLOSED, Ordering::Release);
+        wake_all(&self.shared.version);
+    }
+}
+
+#[allow(dead_code)]
+impl<T> Receiver<T> {
+    pub fn borrow(&self) -> ReadGuard<'_, T> {
+        self.shared.value.read()
+    }
+
+    // marks the current value as seen
+    pub fn borrow_and_update(&mut self) -> ReadGuard<'_, T> {
+        self.seen = self.shared.version.load(Ordering::Acquire) & !CLOSED;
+        self.shared.value.read()
+    }
+
+    pub fn has_changed(&self) -> bool {
+        self.shared.version.load(Ordering::Relaxed) & !CLOSED != self.seen
+    }
+
+    // blocks until a value newer than the last seen one is sent,
+    // fails once the sender is gone and there is nothing new to see
+    pub fn changed(&mut self) -> Result<(), RecvError> {
+        loop {
+            let version = self.shared.version.load(Ordering::Acquire);
+            if version & !CLOSED != self.seen {
+                self.seen = version & !CLOSED;
+                return Ok(());
+            }
+            if version & CLOSED != 0 {
+                return Err(RecvError);
+            }
+            wait(&self.shared.version, version);
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicUsize, thread, time::Duration};
+
+    #[test]
+    fn test_borrow() {
+        let (sender, receiver) = channel(String::from("v1"));
+        assert_eq!(*receiver.borrow(), "v1");
+        assert!(!receiver.has_changed());
+
+        sender.send(String::from("v2"));
+        assert!(receiver.has_changed());
+        assert_eq!(*receiver.borrow(), "v2");
+        assert_eq!(*sender.borrow(), "v2");
+    }
+
+    #[test]
+    fn test_changed() {
+        let (sender, mut receiver) = channel(0);
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                for n in 1..=3 {
+                    thread::sleep(Duration::from_millis(20));
+                    sender.send(n);
+                }
+            });
+
+            // values may be skipped but never go backwards
+            let mut last = 0;
+            while receiver.changed().is_ok() {
+                let value = *receiver.borrow();
+                assert!(value > last);
+                last = value;
+            }
+            assert_eq!(last, 3);
+        });
+    }
+
+    #[test]
+    fn test_subscribe_and_clone() {
+        let (sender, mut receiver) = channel(1);
+        sender.send(2);
+
+        let mut subscribed = sender.subscribe();
+        assert!(!subscribed.has_changed());
+
+        let mut cloned = receiver.clone();
+        assert!(cloned.has_changed());
+        assert_eq!(*cloned.borrow_and_update(), 2);
+        assert!(!cloned.has_changed());
+
+        sender.send(3);
+        drop(sender);
+
+        for receiver in [&mut receiver, &mut subscribed, &mut cloned] {
+            assert_eq!(receiver.changed(), Ok(()));
+            assert_eq!(*receiver.borrow(), 3);
+            assert_eq!(receiver.changed(), Err(RecvError));
+        }
+    }
+
+    #[test]
+    fn test_two_borrows() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DetectDrop(u32);
+
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let (sender_1, receiver_1) = channel(DetectDrop(1));
+        let (sender_2, receiver_2) = channel(DetectDrop(2));
+
+        // every guard has its own hazard, the second must not replace the first
+        let borrow_1 = receiver_1.borrow();
+        let borrow_2 = receiver_2.borrow();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for n in 0..20 {
+                    sender_1.send(DetectDrop(n));
+                    sender_2.send(DetectDrop(n));
+                }
+                sender_1.shared.value.domain.scan_and_reclaim();
+                sender_2.shared.value.domain.scan_and_reclaim();
+            });
+        });
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2 * 19);
+        assert_eq!(borrow_1.0, 1);
+        assert_eq!(borrow_2.0, 2);
+
+        drop(borrow_2);
+        sender_2.shared.value.domain.scan_and_reclaim();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2 * 19 + 1);
+        assert_eq!(borrow_1.0, 1);
+    }
+}